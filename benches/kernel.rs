@@ -0,0 +1,139 @@
+//! Compares the force kernel with the `atan2` based one it replaced, both on
+//! its own and over a stretch of the default run, and direct summation with
+//! the Barnes-Hut tree for clusters of many bodies.
+//!
+//! The default run stretch is a stand-in for the whole run. Setting
+//! `FULL_RUN` also times both kernels once over all `STEPS` of it, which takes
+//! minutes.
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+use macroquad::prelude::*;
+use std::hint::black_box;
+use std::time::Instant;
+use three_bodies::integrator::{Integrator, SymplecticEuler};
+use three_bodies::presets::PRESETS;
+use three_bodies::{Body, Position, Step, STEPS, TIME_STEP};
+
+/// Integration steps of the default run timed per iteration. The full run
+/// takes `STEPS` of them, 1000 times as many.
+const RUN_STEPS: usize = 100000;
+/// Opening angle of the Barnes-Hut tree in the cluster benchmark.
+const OPENING_ANGLE: f64 = 0.5;
+
+/// The previous kernel, which visited each pair twice and projected the force
+/// using the angle between the bodies.
+fn trigonometric_accelerations(step: &Step) -> Vec<Position> {
+    let bodies = &step.bodies;
+    (0..bodies.len())
+        .map(|i| {
+            (0..bodies.len())
+                .filter(|&j| j != i)
+                .fold(Position::ZERO, |sum, j| {
+                    let a = &bodies[j];
+                    let b = &bodies[i];
+                    let dx = a.position.x - b.position.x;
+                    let dy = a.position.y - b.position.y;
+                    let r = (dx * dx + dy * dy).sqrt();
+                    let force = step.gravitational_constant * a.mass * b.mass / r / r;
+                    let angle = dy.atan2(dx);
+                    sum + dvec3(
+                        force * angle.cos() / b.mass,
+                        force * angle.sin() / b.mass,
+                        0.0,
+                    )
+                })
+        })
+        .collect()
+}
+
+/// Symplectic Euler on top of the previous kernel.
+struct TrigonometricEuler;
+
+impl Integrator for TrigonometricEuler {
+    fn integrate(&self, step: &mut Step, time_step: f64) {
+        let accelerations = trigonometric_accelerations(step);
+        for (body, acceleration) in step.bodies.iter_mut().zip(accelerations) {
+            body.velocity += acceleration * time_step;
+            body.position += body.velocity * time_step;
+        }
+    }
+}
+
+fn run(mut step: Step, integrator: &dyn Integrator, steps: usize) -> Step {
+    for _ in 0..steps {
+        step.update(integrator, TIME_STEP).unwrap();
+        step = step.next_step(TIME_STEP);
+    }
+    step
+}
+
+/// `count` equal masses filling a unit ball evenly, placed along a golden
+/// angle spiral at radii that keep the density uniform.
+fn cluster(count: usize) -> Step {
+    let golden_angle = std::f64::consts::PI * (3.0 - 5f64.sqrt());
+    let bodies = (0..count)
+        .map(|i| {
+            let fraction = (i as f64 + 0.5) / count as f64;
+            let z = 1.0 - 2.0 * fraction;
+            let (sin, cos) = (golden_angle * i as f64).sin_cos();
+            let ring = (1.0 - z * z).sqrt();
+            Body::new(fraction.cbrt() * dvec3(ring * cos, ring * sin, z), WHITE)
+        })
+        .collect();
+    Step::new(bodies).with_gravitational_constant(1.0)
+}
+
+fn kernel(c: &mut Criterion) {
+    let step = PRESETS[0].scenario().step;
+
+    let mut group = c.benchmark_group("accelerations");
+    group.bench_function("pairwise", |b| b.iter(|| black_box(&step).accelerations()));
+    group.bench_function("trigonometric", |b| {
+        b.iter(|| trigonometric_accelerations(black_box(&step)))
+    });
+    group.finish();
+
+    let mut group = c.benchmark_group("default run");
+    group.sample_size(10);
+    group.bench_function("pairwise", |b| {
+        b.iter(|| run(black_box(step.clone()), &SymplecticEuler, RUN_STEPS))
+    });
+    group.bench_function("trigonometric", |b| {
+        b.iter(|| run(black_box(step.clone()), &TrigonometricEuler, RUN_STEPS))
+    });
+    group.finish();
+
+    let mut group = c.benchmark_group("cluster");
+    for count in [100, 1000, 10000] {
+        let direct = cluster(count);
+        let tree = direct.clone().with_opening_angle(Some(OPENING_ANGLE));
+        group.bench_with_input(BenchmarkId::new("direct", count), &direct, |b, step| {
+            b.iter(|| black_box(step).accelerations())
+        });
+        group.bench_with_input(BenchmarkId::new("barnes-hut", count), &tree, |b, step| {
+            b.iter(|| black_box(step).accelerations())
+        });
+    }
+    group.finish();
+}
+
+/// Times the whole default run once with each kernel, if `FULL_RUN` is set.
+/// It's too long to repeat as often as criterion needs to.
+fn full_run(_: &mut Criterion) {
+    if std::env::var_os("FULL_RUN").is_none() {
+        return;
+    }
+    let step = PRESETS[0].scenario().step;
+    let kernels: [(&str, &dyn Integrator); 2] = [
+        ("pairwise", &SymplecticEuler),
+        ("trigonometric", &TrigonometricEuler),
+    ];
+    for (name, integrator) in kernels {
+        let start = Instant::now();
+        black_box(run(step.clone(), integrator, STEPS));
+        println!("full run/{}: {:.1?}", name, start.elapsed());
+    }
+}
+
+criterion_group!(benches, kernel, full_run);
+criterion_main!(benches);
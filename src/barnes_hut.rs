@@ -0,0 +1,292 @@
+use crate::{Body, Position, Step};
+
+/// Depth at which bodies are no longer separated, so that bodies sharing a
+/// position share a leaf rather than splitting it forever.
+const MAX_DEPTH: usize = 48;
+
+/// A cube of space holding the total mass of the bodies inside it.
+struct Node {
+    centre: Position,
+    half_size: f64,
+    mass: f64,
+    /// Mass-weighted sum of the positions, the centre of mass once divided by
+    /// `mass`.
+    moment: Position,
+    children: Option<[usize; 8]>,
+    /// Bodies in a leaf, at most one unless the leaf is at `MAX_DEPTH`.
+    bodies: Vec<usize>,
+}
+
+impl Node {
+    fn new(centre: Position, half_size: f64) -> Self {
+        Node {
+            centre,
+            half_size,
+            mass: 0.0,
+            moment: Position::ZERO,
+            children: None,
+            bodies: Vec::new(),
+        }
+    }
+
+    fn octant(&self, position: Position) -> usize {
+        (position.x >= self.centre.x) as usize
+            | ((position.y >= self.centre.y) as usize) << 1
+            | ((position.z >= self.centre.z) as usize) << 2
+    }
+
+    fn contains(&self, position: Position) -> bool {
+        (position - self.centre).abs().max_element() <= self.half_size
+    }
+}
+
+/// An octree over the bodies of a step for Barnes-Hut force evaluation. Far
+/// away groups of bodies pull like a single body at their centre of mass,
+/// which makes a force evaluation O(N log N) instead of O(N²). Bodies in the
+/// xy plane only ever fill four octants, so planar runs get a quadtree.
+pub struct Octree<'a> {
+    step: &'a Step,
+    nodes: Vec<Node>,
+}
+
+impl<'a> Octree<'a> {
+    pub fn new(step: &'a Step) -> Self {
+        let (min, max) = step.bodies.iter().fold(
+            (Position::splat(f64::MAX), Position::splat(f64::MIN)),
+            |(min, max), body| (min.min(body.position), max.max(body.position)),
+        );
+        let half_size = ((max - min).max_element() / 2.0).max(f64::MIN_POSITIVE);
+        let mut tree = Octree {
+            step,
+            nodes: vec![Node::new((min + max) / 2.0, half_size)],
+        };
+        for (index, body) in step.bodies.iter().enumerate() {
+            tree.insert(index, body);
+        }
+        tree
+    }
+
+    fn insert(&mut self, index: usize, body: &Body) {
+        let mut node = 0;
+        for depth in 0.. {
+            self.nodes[node].mass += body.mass;
+            self.nodes[node].moment += body.mass * body.position;
+            if let Some(children) = self.nodes[node].children {
+                node = children[self.nodes[node].octant(body.position)];
+            } else if self.nodes[node].bodies.is_empty() || depth == MAX_DEPTH {
+                self.nodes[node].bodies.push(index);
+                return;
+            } else {
+                let resident = self.nodes[node].bodies.pop().unwrap();
+                let children = self.split(node);
+                let Body { mass, position, .. } = self.step.bodies[resident];
+                let octant = self.nodes[node].octant(position);
+                let child = &mut self.nodes[children[octant]];
+                child.mass = mass;
+                child.moment = mass * position;
+                child.bodies.push(resident);
+                node = children[self.nodes[node].octant(body.position)];
+            }
+        }
+    }
+
+    /// Gives `node` eight empty children.
+    fn split(&mut self, node: usize) -> [usize; 8] {
+        let Node {
+            centre, half_size, ..
+        } = self.nodes[node];
+        let quarter = half_size / 2.0;
+        let children = std::array::from_fn(|octant| {
+            let sign = |bit: usize| if octant & bit == 0 { -1.0 } else { 1.0 };
+            let offset = Position::new(sign(1), sign(2), sign(4)) * quarter;
+            self.nodes.push(Node::new(centre + offset, quarter));
+            self.nodes.len() - 1
+        });
+        self.nodes[node].children = Some(children);
+        children
+    }
+
+    /// Acceleration of body `index` caused by all the others, treating groups
+    /// that look smaller than `opening_angle` radians from the body as one.
+    pub fn acceleration(&self, index: usize, opening_angle: f64) -> Position {
+        self.acceleration_with(index, opening_angle, &mut Vec::new())
+    }
+
+    /// Like `acceleration`, reusing `stack` for the nodes left to visit.
+    fn acceleration_with(
+        &self,
+        index: usize,
+        opening_angle: f64,
+        stack: &mut Vec<usize>,
+    ) -> Position {
+        let softening_squared = self.step.softening * self.step.softening;
+        let pull = |displacement: Position, mass: f64| {
+            let distance_squared = displacement.length_squared() + softening_squared;
+            displacement
+                * (self.step.gravitational_constant * mass
+                    / (distance_squared * distance_squared.sqrt()))
+        };
+
+        let position = self.step.bodies[index].position;
+        let mut acceleration = Position::ZERO;
+        let opening_angle_squared = opening_angle * opening_angle;
+        stack.clear();
+        stack.push(0);
+        while let Some(node) = stack.pop() {
+            let node = &self.nodes[node];
+            if node.mass == 0.0 {
+                continue;
+            }
+            match node.children {
+                None => {
+                    for &other in node.bodies.iter().filter(|&&other| other != index) {
+                        let other = &self.step.bodies[other];
+                        acceleration += pull(other.position - position, other.mass);
+                    }
+                }
+                Some(children) => {
+                    let displacement = node.moment / node.mass - position;
+                    let size = 2.0 * node.half_size;
+                    if size * size < opening_angle_squared * displacement.length_squared()
+                        && !node.contains(position)
+                    {
+                        acceleration += pull(displacement, node.mass);
+                    } else {
+                        stack.extend(children);
+                    }
+                }
+            }
+        }
+        acceleration
+    }
+}
+
+/// Acceleration of every body in `step` using a Barnes-Hut octree with the
+/// given opening angle, 0 giving the same result as direct summation. Each
+/// body walks the tree on its own, on several threads with the `parallel`
+/// feature.
+pub fn accelerations(step: &Step, opening_angle: f64) -> Vec<Position> {
+    let tree = Octree::new(step);
+    #[cfg(feature = "parallel")]
+    {
+        use rayon::prelude::*;
+        (0..step.bodies.len())
+            .into_par_iter()
+            .map_init(Vec::new, |stack, index| {
+                tree.acceleration_with(index, opening_angle, stack)
+            })
+            .collect()
+    }
+    #[cfg(not(feature = "parallel"))]
+    {
+        let mut stack = Vec::new();
+        (0..step.bodies.len())
+            .map(|index| tree.acceleration_with(index, opening_angle, &mut stack))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use macroquad::prelude::*;
+
+    /// A cluster of `count` bodies with random masses, spread uniformly in a
+    /// unit cube by a xorshift generator.
+    fn cluster(count: usize, seed: u64) -> Step {
+        let mut state = seed;
+        let mut random = move || {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            (state >> 11) as f64 / (1u64 << 53) as f64
+        };
+        let bodies = (0..count)
+            .map(|_| {
+                let position = dvec3(random(), random(), random()) - 0.5;
+                Body::new(position, WHITE).with_mass(0.5 + random())
+            })
+            .collect();
+        Step::new(bodies).with_gravitational_constant(1.0)
+    }
+
+    /// Median and largest error relative to direct summation.
+    fn errors(step: &Step, opening_angle: f64) -> (f64, f64) {
+        let direct = step.accelerations();
+        let mut errors: Vec<f64> = accelerations(step, opening_angle)
+            .into_iter()
+            .zip(direct)
+            .map(|(tree, direct)| (tree - direct).length() / direct.length())
+            .collect();
+        errors.sort_by(f64::total_cmp);
+        (errors[errors.len() / 2], errors[errors.len() - 1])
+    }
+
+    #[test]
+    fn test_matches_direct_summation() {
+        for seed in [1, 2, 3] {
+            let step = cluster(300, seed);
+            let (_, largest) = errors(&step, 0.0);
+            assert!(largest < 1e-12, "{}", largest);
+
+            let (median, largest) = errors(&step, 0.5);
+            assert!(median < 1e-2, "{}", median);
+            assert!(largest < 1e-1, "{}", largest);
+
+            let (fine, _) = errors(&step, 0.25);
+            let (coarse, _) = errors(&step, 1.0);
+            assert!(
+                fine < median && median < coarse,
+                "{} {} {}",
+                fine,
+                median,
+                coarse
+            );
+        }
+    }
+
+    #[test]
+    fn test_direct_acceleration_matches_pairwise() {
+        let step = cluster(200, 5).with_softening(0.01);
+        let pairwise = step.pairwise_accelerations();
+        for (i, acceleration) in pairwise.into_iter().enumerate() {
+            assert_eq!(step.direct_acceleration(i), acceleration);
+        }
+    }
+
+    #[cfg(feature = "parallel")]
+    #[test]
+    fn test_independent_of_thread_count() {
+        let direct = cluster(500, 6);
+        let tree = direct.clone().with_opening_angle(Some(0.5));
+        let run = |threads: usize| {
+            rayon::ThreadPoolBuilder::new()
+                .num_threads(threads)
+                .build()
+                .unwrap()
+                .install(|| (direct.accelerations(), tree.accelerations()))
+        };
+
+        let single = run(1);
+        assert_eq!(single.0, direct.pairwise_accelerations());
+        for threads in [2, 3, 8] {
+            assert!(run(threads) == single, "{} threads", threads);
+        }
+    }
+
+    #[test]
+    fn test_planar_and_coincident() {
+        let mut step = cluster(100, 4);
+        for body in step.bodies.iter_mut() {
+            body.position.z = 0.0;
+        }
+        step.bodies.push(step.bodies[0]);
+        let step = step.with_softening(0.01);
+
+        let tree = accelerations(&step, 0.5);
+        assert!(tree.iter().all(|acceleration| acceleration.z == 0.0));
+        let (median, _) = errors(&step, 0.5);
+        assert!(median < 1e-2, "{}", median);
+        assert_eq!(tree[0], tree[100]);
+    }
+}
@@ -0,0 +1,174 @@
+use crate::diagnostics::Diagnostics;
+use crate::{Body, Step};
+use macroquad::prelude::*;
+
+/// Part of the screen kept free around the bodies when fitting them in view.
+const FIT_MARGIN: f64 = 0.8;
+/// How quickly automatic camera moves catch up, per frame.
+const SMOOTHING: f64 = 0.1;
+const ZOOM_FACTOR: f64 = 1.1;
+
+/// What the camera keeps in view.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CameraMode {
+    /// Stays where it was panned and zoomed to.
+    Free,
+    /// Pans and zooms to keep every body on screen.
+    AutoFit,
+    CentreOfMass,
+    /// Follows the body with the given id.
+    Body(usize),
+}
+
+impl CameraMode {
+    /// Follows the body of `step` after the one followed now, or the first
+    /// body if none is.
+    pub fn next_body(self, step: &Step) -> CameraMode {
+        let next = match self {
+            CameraMode::Body(id) => step.bodies.iter().find(|body| body.id > id),
+            _ => None,
+        };
+        next.or(step.bodies.first())
+            .map_or(self, |body| CameraMode::Body(body.id))
+    }
+}
+
+/// A 2D camera looking down on the xy plane that can be panned, zoomed and
+/// made to follow the bodies.
+pub struct Camera {
+    pub mode: CameraMode,
+    centre: DVec2,
+    /// Screen pixels per simulation unit of length.
+    scale: f64,
+    drag: Option<Vec2>,
+}
+
+impl Camera {
+    pub fn new(mode: CameraMode) -> Self {
+        Camera {
+            mode,
+            centre: DVec2::ZERO,
+            scale: 100.,
+            drag: None,
+        }
+    }
+
+    /// Moves the camera to follow `step`, first handling mouse and keyboard
+    /// input if `input` is set.
+    pub fn update(&mut self, step: &Step, input: bool) {
+        if input {
+            self.handle_input(step);
+        }
+
+        match self.mode {
+            CameraMode::Free => (),
+            CameraMode::AutoFit => {
+                if let Some((centre, scale)) =
+                    fit(step, screen_width() as f64, screen_height() as f64)
+                {
+                    self.centre = self.centre.lerp(centre, SMOOTHING);
+                    self.scale *= (scale / self.scale).powf(SMOOTHING);
+                }
+            }
+            CameraMode::CentreOfMass => {
+                self.centre = Diagnostics::new(step).centre_of_mass.truncate();
+            }
+            CameraMode::Body(id) => match Body::find(&step.bodies, id) {
+                Some(n) => self.centre = step.bodies[n].position.truncate(),
+                None => self.mode = CameraMode::Free,
+            },
+        }
+    }
+
+    fn handle_input(&mut self, step: &Step) {
+        if is_key_pressed(KeyCode::F) {
+            self.mode = CameraMode::AutoFit;
+        }
+        if is_key_pressed(KeyCode::C) {
+            self.mode = CameraMode::CentreOfMass;
+        }
+        if is_key_pressed(KeyCode::B) {
+            self.mode = self.mode.next_body(step);
+        }
+
+        let (_, wheel) = mouse_wheel();
+        if wheel != 0. {
+            let cursor = self.screen_to_world(mouse_position().into());
+            self.scale *= ZOOM_FACTOR.powf(wheel.signum() as f64);
+            match self.mode {
+                CameraMode::Free | CameraMode::AutoFit => {
+                    // Keep the point under the cursor where it is.
+                    self.mode = CameraMode::Free;
+                    self.centre += cursor - self.screen_to_world(mouse_position().into());
+                }
+                _ => (),
+            }
+        }
+
+        let mouse: Vec2 = mouse_position().into();
+        if is_mouse_button_down(MouseButton::Left) {
+            if let Some(from) = self.drag {
+                if from != mouse {
+                    self.mode = CameraMode::Free;
+                    self.centre -= (mouse - from).as_dvec2() / self.scale;
+                }
+            }
+            self.drag = Some(mouse);
+        } else {
+            self.drag = None;
+        }
+    }
+
+    /// Simulation units of length covered by one screen pixel.
+    pub fn pixel_size(&self) -> f32 {
+        (1. / self.scale) as f32
+    }
+
+    pub fn camera_2d(&self) -> Camera2D {
+        let scale = self.scale as f32;
+        Camera2D {
+            target: self.centre.as_vec2(),
+            zoom: vec2(2. * scale / screen_width(), -2. * scale / screen_height()),
+            ..Default::default()
+        }
+    }
+
+    fn screen_to_world(&self, point: Vec2) -> DVec2 {
+        let offset = point - vec2(screen_width(), screen_height()) / 2.;
+        self.centre + offset.as_dvec2() / self.scale
+    }
+}
+
+/// Centre and scale that fit all bodies, as seen from above, on a screen of
+/// the given size.
+fn fit(step: &Step, width: f64, height: f64) -> Option<(DVec2, f64)> {
+    let mut bodies = step.bodies.iter().map(|body| body.position.truncate());
+    let first = bodies.next()?;
+    let (min, max) = bodies.fold((first, first), |(min, max), position| {
+        (min.min(position), max.max(position))
+    });
+    let size = (max - min).max(DVec2::splat(1e-9));
+    let scale = (FIT_MARGIN * width / size.x).min(FIT_MARGIN * height / size.y);
+    Some(((min + max) / 2., scale))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Body;
+
+    #[test]
+    fn test_fit() {
+        let step = Step::new(vec![
+            Body::new(dvec3(-1.0, 0.0, 0.0), RED),
+            Body::new(dvec3(3.0, 1.0, 0.0), GREEN),
+            Body::new(dvec3(0.0, -1.0, 0.0), BLUE),
+        ]);
+
+        assert_eq!(
+            fit(&step, 800., 600.),
+            Some((dvec2(1.0, 0.0), 0.8 * 800. / 4.))
+        );
+        assert_eq!(fit(&Step::new(vec![]), 800., 600.), None);
+    }
+}
@@ -0,0 +1,350 @@
+use crate::collision::Collisions;
+use crate::encounter::Encounter;
+use crate::escape::Escape;
+use crate::lyapunov::Lyapunov;
+use crate::scenario::Settings;
+use crate::{Body, Step};
+use macroquad::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+
+/// The full state of a run, written periodically so a long simulation can be
+/// interrupted and carried on with `Simulation::resume`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Checkpoint {
+    pub settings: Settings,
+    pub step: Step,
+    /// Step the run started from, which drift is measured against.
+    pub initial: Step,
+    /// Simulation time at which the next sample is due.
+    pub next_sample: f64,
+    /// Number of integration steps taken so far.
+    pub count: usize,
+    /// Bodies that have escaped so far, if escapes are looked for.
+    pub escapes: Vec<Escape>,
+    /// Close encounters under way, if encounters are logged.
+    pub encounters: Vec<Encounter>,
+    /// Shadows of the run, if its Lyapunov exponent is estimated.
+    pub lyapunov: Option<Lyapunov>,
+    /// How much of its output a headless run writing to a file had written.
+    pub output: Option<Output>,
+}
+
+/// The part of an output file written before a checkpoint was taken, which a
+/// resumed run carries on from.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Output {
+    /// Absolute path of the file.
+    pub path: PathBuf,
+    /// Length of the file in bytes.
+    pub length: u64,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct CheckpointFile {
+    settings: Settings,
+    step: StepFile,
+    initial: StepFile,
+    next_sample: f64,
+    count: usize,
+    #[serde(default)]
+    escapes: Vec<EscapeFile>,
+    #[serde(default)]
+    encounters: Vec<EncounterFile>,
+    #[serde(default)]
+    lyapunov: Option<LyapunovFile>,
+    #[serde(default)]
+    output: Option<OutputFile>,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct OutputFile {
+    path: PathBuf,
+    length: u64,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct EncounterFile {
+    bodies: [usize; 2],
+    time: f64,
+    distance: f64,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct EscapeFile {
+    body: usize,
+    time: f64,
+    velocity: [f64; 3],
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct StepFile {
+    time: f64,
+    step: u32,
+    gravitational_constant: f64,
+    softening: f64,
+    collisions: Collisions,
+    opening_angle: Option<f64>,
+    bodies: Vec<BodyFile>,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct LyapunovFile {
+    shadow: StepFile,
+    overlay: StepFile,
+    separation: f64,
+    log_growth: f64,
+    current_growth: f64,
+    start: f64,
+    time: f64,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct BodyFile {
+    mass: f64,
+    position: [f64; 3],
+    velocity: [f64; 3],
+    color: [f32; 4],
+    radius: f64,
+    /// Missing from checkpoints written before bodies had ids, which had
+    /// them in order.
+    #[serde(default)]
+    id: Option<usize>,
+}
+
+impl From<&Step> for StepFile {
+    fn from(step: &Step) -> Self {
+        StepFile {
+            time: step.time,
+            step: step.step,
+            gravitational_constant: step.gravitational_constant,
+            softening: step.softening,
+            collisions: step.collisions,
+            opening_angle: step.opening_angle,
+            bodies: step
+                .bodies
+                .iter()
+                .map(|body| BodyFile {
+                    mass: body.mass,
+                    position: body.position.into(),
+                    velocity: body.velocity.into(),
+                    color: body.color.into(),
+                    radius: body.radius,
+                    id: Some(body.id),
+                })
+                .collect(),
+        }
+    }
+}
+
+impl From<StepFile> for Step {
+    fn from(file: StepFile) -> Self {
+        let bodies = file
+            .bodies
+            .into_iter()
+            .enumerate()
+            .map(|(n, body)| {
+                Body::new(body.position.into(), Color::from(body.color))
+                    .with_mass(body.mass)
+                    .with_velocity(body.velocity.into())
+                    .with_radius(body.radius)
+                    .with_id(body.id.unwrap_or(n))
+            })
+            .collect();
+        let step = Step::new(Vec::new())
+            .with_gravitational_constant(file.gravitational_constant)
+            .with_softening(file.softening)
+            .with_collisions(file.collisions)
+            .with_opening_angle(file.opening_angle);
+        Step {
+            time: file.time,
+            step: file.step,
+            bodies,
+            ..step
+        }
+    }
+}
+
+impl Checkpoint {
+    /// Writes the checkpoint as JSON, replacing `path` only once the whole
+    /// file has been written so an interruption never leaves it half done.
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), String> {
+        let path = path.as_ref();
+        let mut temporary = path.as_os_str().to_owned();
+        temporary.push(".tmp");
+        std::fs::write(&temporary, self.to_json())
+            .and_then(|_| std::fs::rename(&temporary, path))
+            .map_err(|error| format!("Unable to write {}: {}", path.display(), error))
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, String> {
+        let path = path.as_ref();
+        let contents = std::fs::read_to_string(path)
+            .map_err(|error| format!("Unable to read {}: {}", path.display(), error))?;
+        Checkpoint::from_json(&contents)
+            .map_err(|error| format!("Invalid checkpoint {}: {}", path.display(), error))
+    }
+
+    pub fn to_json(&self) -> String {
+        let file = CheckpointFile {
+            settings: self.settings,
+            step: (&self.step).into(),
+            initial: (&self.initial).into(),
+            next_sample: self.next_sample,
+            count: self.count,
+            escapes: self
+                .escapes
+                .iter()
+                .map(|escape| EscapeFile {
+                    body: escape.body,
+                    time: escape.time,
+                    velocity: escape.velocity.into(),
+                })
+                .collect(),
+            encounters: self
+                .encounters
+                .iter()
+                .map(|encounter| EncounterFile {
+                    bodies: encounter.bodies.into(),
+                    time: encounter.time,
+                    distance: encounter.distance,
+                })
+                .collect(),
+            lyapunov: self.lyapunov.as_ref().map(|lyapunov| LyapunovFile {
+                shadow: (&lyapunov.shadow).into(),
+                overlay: (&lyapunov.overlay).into(),
+                separation: lyapunov.separation,
+                log_growth: lyapunov.log_growth,
+                current_growth: lyapunov.current_growth,
+                start: lyapunov.start,
+                time: lyapunov.time,
+            }),
+            output: self.output.as_ref().map(|output| OutputFile {
+                path: output.path.clone(),
+                length: output.length,
+            }),
+        };
+        serde_json::to_string(&file).expect("Checkpoints can always be serialised")
+    }
+
+    pub fn from_json(contents: &str) -> Result<Self, String> {
+        let file: CheckpointFile =
+            serde_json::from_str(contents).map_err(|error| error.to_string())?;
+        file.settings.validate()?;
+        if file.step.bodies.is_empty() {
+            return Err("Checkpoint has no bodies".to_string());
+        }
+        Ok(Checkpoint {
+            settings: file.settings,
+            step: file.step.into(),
+            initial: file.initial.into(),
+            next_sample: file.next_sample,
+            count: file.count,
+            escapes: file
+                .escapes
+                .into_iter()
+                .map(|escape| Escape {
+                    body: escape.body,
+                    time: escape.time,
+                    velocity: escape.velocity.into(),
+                })
+                .collect(),
+            encounters: file
+                .encounters
+                .into_iter()
+                .map(|encounter| Encounter {
+                    bodies: encounter.bodies.into(),
+                    time: encounter.time,
+                    distance: encounter.distance,
+                })
+                .collect(),
+            lyapunov: file.lyapunov.map(|lyapunov| Lyapunov {
+                shadow: lyapunov.shadow.into(),
+                overlay: lyapunov.overlay.into(),
+                separation: lyapunov.separation,
+                log_growth: lyapunov.log_growth,
+                current_growth: lyapunov.current_growth,
+                start: lyapunov.start,
+                time: lyapunov.time,
+            }),
+            output: file.output.map(|output| Output {
+                path: output.path,
+                length: output.length,
+            }),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::integrator::Scheme;
+    use crate::presets::{Preset, PRESETS};
+    use crate::simulation::Simulation;
+
+    #[test]
+    fn test_resume_matches_uninterrupted_run() {
+        for (tolerance, lyapunov) in [(None, false), (Some(0.01), false), (None, true)] {
+            let mut scenario = PRESETS[Preset::find("pythagorean").unwrap()].scenario();
+            scenario.settings.integrator = Scheme::RungeKutta4;
+            scenario.settings.time_step = 1e-4;
+            scenario.settings.steps = 100_000;
+            scenario.settings.tolerance = tolerance;
+            // Bodies 2 and 3 start out 3 apart and move away from each
+            // other, so their encounter is under way at the checkpoint with
+            // its closest approach before it.
+            scenario.settings.encounter_distance = Some(3.5);
+            scenario.step.bodies[2].velocity.x = 1.0;
+            scenario.settings.lyapunov = lyapunov;
+            let settings = scenario.settings;
+            let new = || Simulation::from_settings(scenario.step.clone(), &settings);
+
+            let mut uninterrupted = new();
+            let expected: Vec<Step> = uninterrupted
+                .by_ref()
+                .take(20)
+                .map(Result::unwrap)
+                .collect();
+
+            let mut interrupted = new();
+            let mut samples: Vec<Step> = interrupted.by_ref().take(8).map(Result::unwrap).collect();
+            for _ in 0..7 {
+                samples.extend(interrupted.advance().unwrap());
+            }
+            let json = interrupted.checkpoint(settings).to_json();
+            drop(interrupted);
+
+            let checkpoint = Checkpoint::from_json(&json).unwrap();
+            assert_eq!(checkpoint.settings, settings);
+            assert!(!checkpoint.encounters.is_empty());
+            let mut resumed = Simulation::resume(checkpoint);
+            samples.extend(
+                resumed
+                    .by_ref()
+                    .take(expected.len() - samples.len())
+                    .map(Result::unwrap),
+            );
+            // Encounters under way carry on with their closest approach so
+            // far rather than starting over.
+            assert_eq!(
+                resumed.checkpoint(settings).encounters,
+                uninterrupted.checkpoint(settings).encounters
+            );
+            assert_eq!(resumed.lyapunov(), uninterrupted.lyapunov());
+            assert_eq!(resumed.lyapunov().is_some(), lyapunov);
+
+            assert_eq!(
+                samples, expected,
+                "tolerance {:?}, lyapunov {}",
+                tolerance, lyapunov
+            );
+        }
+    }
+}
@@ -0,0 +1,207 @@
+use crate::{Body, Step};
+use macroquad::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::f64::consts::PI;
+use std::fmt;
+use std::str::FromStr;
+
+/// What happens when two bodies touch.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Collisions {
+    /// Bodies pass through each other.
+    #[default]
+    #[serde(rename = "none")]
+    PassThrough,
+    /// Perfectly inelastic: the bodies become one, conserving mass, momentum
+    /// and volume.
+    #[serde(rename = "merge")]
+    Merge,
+    /// Perfectly elastic bounce off each other.
+    #[serde(rename = "bounce")]
+    Bounce,
+}
+
+impl Collisions {
+    pub const ALL: [Collisions; 3] = [
+        Collisions::PassThrough,
+        Collisions::Merge,
+        Collisions::Bounce,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Collisions::PassThrough => "none",
+            Collisions::Merge => "merge",
+            Collisions::Bounce => "bounce",
+        }
+    }
+
+    /// Resolves every collision between the bodies of `step`.
+    pub(crate) fn resolve(self, step: &mut Step) {
+        match self {
+            Collisions::PassThrough => (),
+            Collisions::Merge => {
+                while let Some((i, j)) = first_touching(&step.bodies) {
+                    let other = step.bodies.remove(j);
+                    step.bodies[i] = merge(&step.bodies[i], &other);
+                    info!(
+                        "Bodies {} and {} merged at t = {}",
+                        step.bodies[i].id + 1,
+                        other.id + 1,
+                        step.time
+                    );
+                }
+            }
+            Collisions::Bounce => {
+                let pairs: Vec<_> = touching(&step.bodies).collect();
+                for (i, j) in pairs {
+                    let (head, tail) = step.bodies.split_at_mut(j);
+                    bounce(&mut head[i], &mut tail[0]);
+                }
+            }
+        }
+    }
+}
+
+impl fmt::Display for Collisions {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+impl FromStr for Collisions {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        Collisions::ALL
+            .into_iter()
+            .find(|collisions| collisions.name() == name)
+            .ok_or_else(|| {
+                let names: Vec<_> = Collisions::ALL.iter().map(|c| c.name()).collect();
+                format!(
+                    "Unknown collision policy '{}', expected one of: {}",
+                    name,
+                    names.join(", ")
+                )
+            })
+    }
+}
+
+/// Radius of a sphere of `mass` with uniform `density`.
+pub fn radius_from_density(mass: f64, density: f64) -> f64 {
+    (3.0 * mass / (4.0 * PI * density)).cbrt()
+}
+
+/// Pairs of bodies that overlap, the lower index first.
+fn touching(bodies: &[Body]) -> impl Iterator<Item = (usize, usize)> + '_ {
+    bodies.iter().enumerate().flat_map(move |(i, a)| {
+        bodies
+            .iter()
+            .enumerate()
+            .skip(i + 1)
+            .filter(move |(_, b)| a.position.distance(b.position) < a.radius + b.radius)
+            .map(move |(j, _)| (i, j))
+    })
+}
+
+fn first_touching(bodies: &[Body]) -> Option<(usize, usize)> {
+    touching(bodies).next()
+}
+
+/// A single body with the combined mass, momentum and volume of `a` and `b`,
+/// at their centre of mass and in the colour of the heavier one. It keeps the
+/// id of `a`, which comes first.
+fn merge(a: &Body, b: &Body) -> Body {
+    let mass = a.mass + b.mass;
+    let heavier = if b.mass > a.mass { b } else { a };
+    Body::new(
+        (a.position * a.mass + b.position * b.mass) / mass,
+        heavier.color,
+    )
+    .with_mass(mass)
+    .with_velocity((a.velocity * a.mass + b.velocity * b.mass) / mass)
+    .with_radius((a.radius.powi(3) + b.radius.powi(3)).cbrt())
+    .with_id(a.id)
+}
+
+/// Reflects the velocities of `a` and `b` along the line between them if they
+/// are moving towards each other.
+fn bounce(a: &mut Body, b: &mut Body) {
+    let normal = (b.position - a.position).normalize_or_zero();
+    let approach = (a.velocity - b.velocity).dot(normal);
+    if approach > 0.0 {
+        let impulse = 2.0 * approach / (a.mass + b.mass);
+        a.velocity -= normal * impulse * b.mass;
+        b.velocity += normal * impulse * a.mass;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::diagnostics::Diagnostics;
+    use crate::integrator::Scheme;
+
+    fn head_on(collisions: Collisions) -> Step {
+        Step::new(vec![
+            Body::new(dvec3(-1.0, 0.0, 0.0), RED)
+                .with_velocity(dvec3(1.0, 0.1, 0.0))
+                .with_radius(0.1),
+            Body::new(dvec3(1.0, 0.0, 0.0), GREEN)
+                .with_mass(3.0)
+                .with_velocity(dvec3(-1.0, 0.0, 0.0))
+                .with_radius(0.2),
+            Body::new(dvec3(0.0, 50.0, 0.0), BLUE),
+        ])
+        .with_gravitational_constant(1e-6)
+        .with_collisions(collisions)
+    }
+
+    fn run(mut step: Step) -> Step {
+        for _ in 0..200 {
+            step.update(&Scheme::VelocityVerlet, 0.01).unwrap();
+            step = step.next_step(0.01);
+        }
+        step
+    }
+
+    #[test]
+    fn test_merge() {
+        let initial = head_on(Collisions::Merge);
+        let step = run(initial.clone());
+
+        assert_eq!(step.bodies.len(), 2);
+        let merged = step.bodies[0];
+        assert_eq!(merged.mass, 4.0);
+        assert_eq!(merged.color, GREEN);
+        assert_eq!(merged.id, 0);
+        assert_eq!(step.bodies[1].id, 2);
+        assert!((merged.radius - 0.009f64.cbrt()).abs() < 1e-12);
+
+        let before = Diagnostics::new(&initial);
+        let after = Diagnostics::new(&step);
+        assert!((before.momentum - after.momentum).length() < 1e-9);
+    }
+
+    #[test]
+    fn test_bounce() {
+        let initial = head_on(Collisions::Bounce);
+        let step = run(initial.clone());
+
+        assert_eq!(step.bodies.len(), 3);
+        assert!(step.bodies[0].velocity.x < 0.0);
+        assert!(step.bodies[0].position.x < step.bodies[1].position.x);
+
+        let before = Diagnostics::new(&initial);
+        let after = Diagnostics::new(&step);
+        assert!((before.momentum - after.momentum).length() < 1e-9);
+        assert!((before.kinetic_energy - after.kinetic_energy).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_pass_through() {
+        let step = run(head_on(Collisions::PassThrough));
+        assert_eq!(step.bodies.len(), 3);
+        assert!(step.bodies[0].position.x > step.bodies[1].position.x);
+    }
+}
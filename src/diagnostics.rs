@@ -0,0 +1,223 @@
+use crate::{Position, Step};
+use macroquad::prelude::*;
+use std::fmt;
+
+/// Conserved quantities of a `Step`, used to judge whether a run can be trusted.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Diagnostics {
+    pub kinetic_energy: f64,
+    pub potential_energy: f64,
+    pub momentum: Position,
+    pub angular_momentum: Position,
+    pub centre_of_mass: Position,
+}
+
+impl Diagnostics {
+    pub fn new(step: &Step) -> Self {
+        let bodies = &step.bodies;
+        let total_mass: f64 = bodies.iter().map(|body| body.mass).sum();
+
+        let mut diagnostics = Diagnostics {
+            kinetic_energy: 0.0,
+            potential_energy: 0.0,
+            momentum: Position::ZERO,
+            angular_momentum: Position::ZERO,
+            centre_of_mass: Position::ZERO,
+        };
+        for (i, body) in bodies.iter().enumerate() {
+            diagnostics.kinetic_energy += 0.5 * body.mass * body.velocity.length_squared();
+            diagnostics.momentum += body.mass * body.velocity;
+            diagnostics.angular_momentum += body.mass * body.position.cross(body.velocity);
+            diagnostics.centre_of_mass += body.mass * body.position;
+            for other in bodies[i + 1..].iter() {
+                let distance = (body.position.distance_squared(other.position)
+                    + step.softening * step.softening)
+                    .sqrt();
+                diagnostics.potential_energy -=
+                    step.gravitational_constant * body.mass * other.mass / distance;
+            }
+        }
+        if total_mass > 0.0 {
+            diagnostics.centre_of_mass /= total_mass;
+        }
+
+        diagnostics
+    }
+
+    pub fn energy(&self) -> f64 {
+        self.kinetic_energy + self.potential_energy
+    }
+
+    /// How far these diagnostics have moved away from the `initial` ones.
+    pub fn drift(&self, initial: &Diagnostics) -> Drift {
+        Drift {
+            energy: relative_change(
+                (self.energy() - initial.energy()).abs(),
+                initial.energy().abs(),
+            ),
+            momentum: relative_change(
+                self.momentum.distance(initial.momentum),
+                initial.momentum.length(),
+            ),
+            angular_momentum: relative_change(
+                self.angular_momentum.distance(initial.angular_momentum),
+                initial.angular_momentum.length(),
+            ),
+        }
+    }
+
+    pub fn draw(&self, drift: &Drift, max_drift: &Drift, notes: &[String]) {
+        let lines = [
+            format!(
+                "Energy: {:.6e} (kinetic {:.4e}, potential {:.4e})",
+                self.energy(),
+                self.kinetic_energy,
+                self.potential_energy
+            ),
+            format!(
+                "Momentum: ({:.4e}, {:.4e}, {:.4e})",
+                self.momentum.x, self.momentum.y, self.momentum.z
+            ),
+            format!(
+                "Angular momentum: ({:.4e}, {:.4e}, {:.6e})",
+                self.angular_momentum.x, self.angular_momentum.y, self.angular_momentum.z
+            ),
+            format!(
+                "Centre of mass: ({:.4}, {:.4}, {:.4})",
+                self.centre_of_mass.x, self.centre_of_mass.y, self.centre_of_mass.z
+            ),
+            format!("Drift: {}", drift),
+            format!("Max drift: {}", max_drift),
+        ];
+        for (n, line) in lines.iter().chain(notes).enumerate() {
+            draw_text(line, 10., 20. + n as f32 * 18., 18., DARKGRAY);
+        }
+    }
+}
+
+/// Relative change of the conserved quantities compared to the start of a run.
+///
+/// Quantities that start out at zero are compared absolutely instead.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Drift {
+    pub energy: f64,
+    pub momentum: f64,
+    pub angular_momentum: f64,
+}
+
+impl Drift {
+    pub fn max(self, other: Drift) -> Drift {
+        Drift {
+            energy: self.energy.max(other.energy),
+            momentum: self.momentum.max(other.momentum),
+            angular_momentum: self.angular_momentum.max(other.angular_momentum),
+        }
+    }
+}
+
+impl fmt::Display for Drift {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "energy {:.3e}, momentum {:.3e}, angular momentum {:.3e}",
+            self.energy, self.momentum, self.angular_momentum
+        )
+    }
+}
+
+/// `change` relative to the `initial` size of a quantity, or absolute if it
+/// started out at zero.
+fn relative_change(change: f64, initial: f64) -> f64 {
+    if initial > f64::EPSILON {
+        change / initial
+    } else {
+        change
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::integrator::Scheme;
+    use crate::presets::{Preset, PRESETS};
+    use crate::simulation::Simulation;
+    use crate::time_step::TimeStep;
+    use crate::GRAVITATIONAL_CONSTANT;
+    use crate::{simulate, Body};
+
+    #[test]
+    fn test_conserved_quantities() {
+        let mut first = Body::new(dvec3(-0.5, 0.0, 0.0), RED);
+        first.velocity = dvec3(0.0, -5e-6, 0.0);
+        let mut second = Body::new(dvec3(0.5, 0.0, 0.0), BLUE);
+        second.velocity = dvec3(0.0, 5e-6, 0.0);
+        let initial_step = Step::new(vec![first, second]);
+
+        let initial = Diagnostics::new(&initial_step);
+        assert_eq!(initial.momentum, Position::ZERO);
+        assert_eq!(initial.centre_of_mass, Position::ZERO);
+        assert!((initial.angular_momentum - dvec3(0.0, 0.0, 5e-6)).length() < 1e-20);
+        assert!((initial.kinetic_energy - 2.5e-11).abs() < 1e-25);
+        assert_eq!(initial.potential_energy, -GRAVITATIONAL_CONSTANT);
+
+        for step in simulate(
+            initial_step,
+            1e6,
+            TimeStep::Fixed(100.0),
+            1e4,
+            &Scheme::VelocityVerlet,
+        )
+        .unwrap()
+        {
+            let drift = Diagnostics::new(&step).drift(&initial);
+            assert!(drift.energy < 1e-6, "{}", drift);
+            assert!(drift.momentum < 1e-18, "{}", drift);
+            assert!(drift.angular_momentum < 1e-9, "{}", drift);
+        }
+    }
+
+    #[test]
+    fn test_out_of_plane() {
+        let scenario = PRESETS[Preset::find("inclined").unwrap()].scenario();
+        let initial = Diagnostics::new(&scenario.step);
+        assert!(initial.angular_momentum.y.abs() > 0.1);
+
+        let steps = simulate(
+            scenario.step,
+            10.0,
+            TimeStep::Fixed(1e-3),
+            1.0,
+            &Scheme::RungeKutta4,
+        )
+        .unwrap();
+        assert!(steps.iter().any(|step| !step.is_planar()));
+        for step in steps {
+            let drift = Diagnostics::new(&step).drift(&initial);
+            assert!(drift.energy < 1e-9, "{}", drift);
+            assert!(drift.angular_momentum < 1e-9, "{}", drift);
+        }
+    }
+
+    #[test]
+    fn test_max_drift() {
+        // Far shorter than a million steps, so the drift has to be tracked
+        // at every sample to be seen at all.
+        let scenario = PRESETS[Preset::find("figure-eight").unwrap()].scenario();
+        let initial = Diagnostics::new(&scenario.step);
+        let mut simulation = Simulation::new(
+            scenario.step,
+            TimeStep::Fixed(0.01),
+            0.1,
+            Scheme::SymplecticEuler,
+        );
+        let mut max_drift = Drift::default();
+        simulation
+            .run_until(2.0, |sample| {
+                max_drift = max_drift.max(Diagnostics::new(&sample).drift(&initial));
+                Ok(())
+            })
+            .unwrap();
+        assert!(max_drift.energy > 1e-6, "{}", max_drift);
+        assert_eq!(simulation.max_drift(), max_drift);
+    }
+}
@@ -0,0 +1,132 @@
+use crate::{Body, Step};
+use std::fmt;
+
+/// Two bodies passing closer to each other than the encounter distance.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Encounter {
+    /// Ids of the two bodies, the lower one first.
+    pub bodies: (usize, usize),
+    /// Simulation time of the closest approach.
+    pub time: f64,
+    /// Distance between the bodies at their closest approach.
+    pub distance: f64,
+}
+
+impl fmt::Display for Encounter {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "Close encounter between bodies {} and {}, closest approach {:.4e} at t = {}",
+            self.bodies.0 + 1,
+            self.bodies.1 + 1,
+            self.distance,
+            self.time
+        )
+    }
+}
+
+/// Follows pairs of bodies while they are within `distance` of each other.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Encounters {
+    distance: f64,
+    ongoing: Vec<Encounter>,
+}
+
+impl Encounters {
+    pub fn new(distance: f64) -> Self {
+        Encounters {
+            distance,
+            ongoing: Vec::new(),
+        }
+    }
+
+    /// Carries on following encounters that were already under way.
+    pub fn with_ongoing(self, ongoing: Vec<Encounter>) -> Self {
+        Encounters { ongoing, ..self }
+    }
+
+    /// Encounters that haven't ended yet, each with its closest approach so
+    /// far.
+    pub fn ongoing(&self) -> &[Encounter] {
+        &self.ongoing
+    }
+
+    /// Checks every pair of bodies in `step`, returning the encounters that
+    /// have just ended.
+    pub fn update(&mut self, step: &Step) -> Vec<Encounter> {
+        for (i, a) in step.bodies.iter().enumerate() {
+            for b in &step.bodies[i + 1..] {
+                let distance = a.position.distance(b.position);
+                if distance >= self.distance {
+                    continue;
+                }
+                let closest = Encounter {
+                    bodies: (a.id, b.id),
+                    time: step.time,
+                    distance,
+                };
+                match self
+                    .ongoing
+                    .iter_mut()
+                    .find(|ongoing| ongoing.bodies == (a.id, b.id))
+                {
+                    Some(ongoing) if distance < ongoing.distance => *ongoing = closest,
+                    Some(_) => (),
+                    None => self.ongoing.push(closest),
+                }
+            }
+        }
+
+        // Bodies that have merged away end their encounters too.
+        let (ended, ongoing) = self.ongoing.iter().partition(|encounter| {
+            let (i, j) = encounter.bodies;
+            match (Body::find(&step.bodies, i), Body::find(&step.bodies, j)) {
+                (Some(a), Some(b)) => {
+                    step.bodies[a].position.distance(step.bodies[b].position) >= self.distance
+                }
+                _ => true,
+            }
+        });
+        self.ongoing = ongoing;
+        ended
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::integrator::Scheme;
+    use crate::simulate;
+    use crate::time_step::TimeStep;
+    use macroquad::prelude::*;
+
+    #[test]
+    fn test_close_approach() {
+        // Two bodies falling towards each other past a third, far away one.
+        let step = Step::new(vec![
+            Body::new(dvec3(-1.0, 0.01, 0.0), RED).with_velocity(dvec3(1.0, 0.0, 0.0)),
+            Body::new(dvec3(1.0, -0.01, 0.0), GREEN).with_velocity(dvec3(-1.0, 0.0, 0.0)),
+            Body::new(dvec3(0.0, 100.0, 0.0), BLUE),
+        ])
+        .with_gravitational_constant(1e-6);
+        let steps = simulate(
+            step,
+            2.0,
+            TimeStep::Fixed(1e-4),
+            1e-4,
+            &Scheme::VelocityVerlet,
+        )
+        .unwrap();
+
+        let mut encounters = Encounters::new(0.1);
+        let ended: Vec<_> = steps
+            .iter()
+            .flat_map(|step| encounters.update(step))
+            .collect();
+
+        assert_eq!(ended.len(), 1);
+        assert_eq!(ended[0].bodies, (0, 1));
+        assert!((ended[0].time - 1.0).abs() < 1e-3, "{}", ended[0]);
+        assert!((ended[0].distance - 0.02).abs() < 1e-4, "{}", ended[0]);
+    }
+}
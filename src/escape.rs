@@ -0,0 +1,237 @@
+use crate::diagnostics::Diagnostics;
+use crate::{Position, Step};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::str::FromStr;
+
+/// A body counts as escaped once it is this many times further from the
+/// others than the furthest body started out from the centre of mass.
+pub const ESCAPE_FACTOR: f64 = 10.0;
+
+/// What happens when a body escapes.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Escapes {
+    /// Escapes aren't looked for.
+    #[default]
+    #[serde(rename = "none")]
+    Ignore,
+    /// Every escape is logged and the run carries on.
+    #[serde(rename = "log")]
+    Log,
+    /// The run ends at the first escape.
+    #[serde(rename = "stop")]
+    Stop,
+}
+
+impl Escapes {
+    pub const ALL: [Escapes; 3] = [Escapes::Ignore, Escapes::Log, Escapes::Stop];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Escapes::Ignore => "none",
+            Escapes::Log => "log",
+            Escapes::Stop => "stop",
+        }
+    }
+}
+
+impl fmt::Display for Escapes {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+impl FromStr for Escapes {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        Escapes::ALL
+            .into_iter()
+            .find(|escapes| escapes.name() == name)
+            .ok_or_else(|| {
+                let names: Vec<_> = Escapes::ALL.iter().map(|e| e.name()).collect();
+                format!(
+                    "Unknown escape policy '{}', expected one of: {}",
+                    name,
+                    names.join(", ")
+                )
+            })
+    }
+}
+
+/// A body leaving the others for good.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Escape {
+    /// Id of the body that escaped.
+    pub body: usize,
+    /// Simulation time at which it was found to have escaped.
+    pub time: f64,
+    /// Velocity of the body relative to the centre of mass of the others.
+    pub velocity: Position,
+}
+
+impl fmt::Display for Escape {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "Body {} escaped at t = {} with velocity ({:.4e}, {:.4e}, {:.4e}) relative to the others",
+            self.body + 1,
+            self.time,
+            self.velocity.x,
+            self.velocity.y,
+            self.velocity.z
+        )
+    }
+}
+
+/// Looks for bodies escaping, each one reported once.
+#[derive(Debug, Clone, PartialEq)]
+pub struct EscapeDetector {
+    distance: f64,
+    escapes: Vec<Escape>,
+}
+
+impl EscapeDetector {
+    /// Counts bodies further than `distance` from all others as far enough
+    /// away to have escaped.
+    pub fn new(distance: f64) -> Self {
+        EscapeDetector {
+            distance,
+            escapes: Vec::new(),
+        }
+    }
+
+    /// Uses a distance of `ESCAPE_FACTOR` times the furthest any body of
+    /// `initial` is from its centre of mass.
+    pub fn from_initial(initial: &Step) -> Self {
+        let centre_of_mass = Diagnostics::new(initial).centre_of_mass;
+        let extent = initial
+            .bodies
+            .iter()
+            .map(|body| body.position.distance(centre_of_mass))
+            .fold(0.0, f64::max);
+        EscapeDetector::new(ESCAPE_FACTOR * extent)
+    }
+
+    /// Carries on after the escapes that have already been found.
+    pub fn with_escapes(self, escapes: Vec<Escape>) -> Self {
+        EscapeDetector { escapes, ..self }
+    }
+
+    /// Every escape found so far, in the order they happened.
+    pub fn escapes(&self) -> &[Escape] {
+        &self.escapes
+    }
+
+    /// Checks every body in `step`, returning those that have just escaped.
+    pub fn update(&mut self, step: &Step) -> Vec<Escape> {
+        let found: Vec<_> = escapes(step, self.distance)
+            .filter(|escape| self.escapes.iter().all(|known| known.body != escape.body))
+            .collect();
+        self.escapes.extend(found.iter().copied());
+        found
+    }
+}
+
+/// Bodies of `step` further than `distance` from all the others, moving away
+/// from their centre of mass and no longer bound to them.
+pub fn escapes(step: &Step, distance: f64) -> impl Iterator<Item = Escape> + '_ {
+    let total_mass: f64 = step.bodies.iter().map(|body| body.mass).sum();
+    let (moment, momentum) = step.bodies.iter().fold(
+        (Position::ZERO, Position::ZERO),
+        |(moment, momentum), body| {
+            (
+                moment + body.mass * body.position,
+                momentum + body.mass * body.velocity,
+            )
+        },
+    );
+    step.bodies.iter().enumerate().filter_map(move |(i, body)| {
+        let others = total_mass - body.mass;
+        let isolated = step
+            .bodies
+            .iter()
+            .enumerate()
+            .all(|(j, other)| i == j || body.position.distance(other.position) > distance);
+        if others <= 0.0 || !isolated {
+            return None;
+        }
+        let offset = body.position - (moment - body.mass * body.position) / others;
+        let velocity = body.velocity - (momentum - body.mass * body.velocity) / others;
+        let energy = 0.5 * velocity.length_squared()
+            - step.gravitational_constant * total_mass / offset.length();
+        (offset.dot(velocity) > 0.0 && energy > 0.0).then_some(Escape {
+            body: body.id,
+            time: step.time,
+            velocity,
+        })
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::integrator::Scheme;
+    use crate::presets::{Preset, PRESETS};
+    use crate::scenario::Settings;
+    use crate::simulation::Simulation;
+    use crate::Body;
+    use macroquad::prelude::*;
+
+    #[test]
+    fn test_escapes() {
+        let binary = |speed| {
+            Step::new(vec![
+                Body::new(dvec3(-0.5, 0.0, 0.0), RED).with_velocity(dvec3(0.0, -0.5, 0.0)),
+                Body::new(dvec3(0.5, 0.0, 0.0), GREEN).with_velocity(dvec3(0.0, 0.5, 0.0)),
+                Body::new(dvec3(20.0, 0.0, 0.0), BLUE).with_velocity(dvec3(speed, 0.0, 0.0)),
+            ])
+            .with_gravitational_constant(1.0)
+        };
+        let first = |step: &Step, distance| escapes(step, distance).next();
+
+        let escape = first(&binary(1.0), 10.0).unwrap();
+        assert_eq!(escape.body, 2);
+        assert_eq!(escape.velocity, dvec3(1.0, 0.0, 0.0));
+        assert_eq!(first(&binary(1.0), 30.0), None);
+        assert_eq!(first(&binary(0.1), 10.0), None);
+        assert_eq!(first(&binary(-1.0), 10.0), None);
+
+        let mut detector = EscapeDetector::new(10.0);
+        assert_eq!(detector.update(&binary(1.0)), vec![escape]);
+        assert_eq!(detector.update(&binary(1.0)), vec![]);
+        assert_eq!(detector.escapes(), &[escape]);
+    }
+
+    #[test]
+    fn test_stop_on_escape() {
+        // The lightest body of the Pythagorean problem is thrown out for good
+        // a while after t = 60.
+        let scenario = PRESETS[Preset::find("pythagorean").unwrap()].scenario();
+        let settings = Settings {
+            integrator: Scheme::RungeKutta4,
+            tolerance: Some(1e-3),
+            steps: 20_000_000,
+            escapes: Escapes::Stop,
+            ..scenario.settings
+        };
+        let mut simulation = Simulation::from_settings(scenario.step, &settings);
+        let mut samples = Vec::new();
+        simulation
+            .run_until(settings.duration(), |sample| {
+                samples.push(sample);
+                Ok(())
+            })
+            .unwrap();
+
+        assert!(simulation.stopped());
+        assert_eq!(simulation.next(), None);
+        let escapes = simulation.escapes();
+        assert_eq!(escapes.len(), 1);
+        let escape = escapes[0];
+        assert_eq!(escape.body, 0);
+        assert!(escape.time > 60.0 && escape.time < 100.0, "{}", escape);
+        assert!(escape.velocity.length() > 0.0);
+        assert_eq!(samples.last().unwrap().time, escape.time);
+    }
+}
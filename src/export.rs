@@ -0,0 +1,311 @@
+use crate::kepler::{self, Elements};
+use crate::Step;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::io::{self, Write};
+use std::path::Path;
+use std::str::FromStr;
+
+/// Magic bytes at the start of a binary trajectory, followed by a version byte.
+pub const BINARY_MAGIC: &[u8; 4] = b"3BDY";
+pub const BINARY_VERSION: u8 = 3;
+/// First line of CSV output.
+pub const CSV_HEADER: &str = "time,step,body,mass,x,y,z,vx,vy,vz,\
+                              semi_major_axis,eccentricity,argument_of_periapsis,period";
+/// Columns of CSV output holding the state of a body, which come first.
+pub(crate) const CSV_STATE_COLUMNS: usize = 10;
+
+/// File format of an exported trajectory.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Format {
+    /// One row per body and sampled step, ending with the elements of the
+    /// orbit the body is part of when there is one.
+    #[default]
+    Csv,
+    /// One JSON object per sampled step, with the elements of the orbit each
+    /// body is part of when there is one.
+    JsonLines,
+    /// Little-endian records: time as `f64`, step and body count as `u32`,
+    /// then mass, the three components of position and velocity and the
+    /// elements of the orbit of each body as `f64`s, NaN when missing.
+    Binary,
+}
+
+impl Format {
+    pub const ALL: [Format; 3] = [Format::Csv, Format::JsonLines, Format::Binary];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Format::Csv => "csv",
+            Format::JsonLines => "jsonl",
+            Format::Binary => "binary",
+        }
+    }
+
+    /// Guesses the format from the extension of `path`.
+    pub fn from_path(path: impl AsRef<Path>) -> Option<Format> {
+        match path.as_ref().extension()?.to_str()? {
+            "csv" => Some(Format::Csv),
+            "jsonl" | "json" => Some(Format::JsonLines),
+            "bin" => Some(Format::Binary),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Format {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+impl FromStr for Format {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        Format::ALL
+            .into_iter()
+            .find(|format| format.name() == name)
+            .ok_or_else(|| {
+                let names: Vec<_> = Format::ALL.iter().map(|format| format.name()).collect();
+                format!(
+                    "Unknown format '{}', expected one of: {}",
+                    name,
+                    names.join(", ")
+                )
+            })
+    }
+}
+
+/// A line of JSON Lines output.
+#[derive(Serialize, Deserialize)]
+pub(crate) struct StepRecord {
+    pub time: f64,
+    pub step: u32,
+    pub bodies: Vec<BodyRecord>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub(crate) struct BodyRecord {
+    pub mass: f64,
+    pub position: [f64; 3],
+    pub velocity: [f64; 3],
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub orbit: Option<OrbitRecord>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub(crate) struct OrbitRecord {
+    pub semi_major_axis: f64,
+    pub eccentricity: f64,
+    pub argument_of_periapsis: f64,
+    pub period: Option<f64>,
+}
+
+impl From<Elements> for OrbitRecord {
+    fn from(elements: Elements) -> Self {
+        OrbitRecord {
+            semi_major_axis: elements.semi_major_axis,
+            eccentricity: elements.eccentricity,
+            argument_of_periapsis: elements.argument_of_periapsis,
+            period: elements.period,
+        }
+    }
+}
+
+impl From<&Step> for StepRecord {
+    fn from(step: &Step) -> Self {
+        StepRecord {
+            time: step.time,
+            step: step.step,
+            bodies: step
+                .bodies
+                .iter()
+                .zip(kepler::orbits(step))
+                .map(|(body, orbit)| BodyRecord {
+                    mass: body.mass,
+                    position: body.position.into(),
+                    velocity: body.velocity.into(),
+                    orbit: orbit.map(OrbitRecord::from),
+                })
+                .collect(),
+        }
+    }
+}
+
+/// Writes sampled steps to `writer` in the chosen format.
+pub struct Exporter<W: Write> {
+    writer: W,
+    format: Format,
+}
+
+impl<W: Write> Exporter<W> {
+    /// Creates an exporter and writes the header of the format, if any.
+    pub fn new(mut writer: W, format: Format) -> io::Result<Self> {
+        match format {
+            Format::Csv => writeln!(writer, "{}", CSV_HEADER)?,
+            Format::JsonLines => (),
+            Format::Binary => {
+                writer.write_all(BINARY_MAGIC)?;
+                writer.write_all(&[BINARY_VERSION])?;
+            }
+        }
+        Ok(Exporter { writer, format })
+    }
+
+    /// Creates an exporter carrying on output that already has its header.
+    pub fn append(writer: W, format: Format) -> Self {
+        Exporter { writer, format }
+    }
+
+    pub fn write(&mut self, step: &Step) -> io::Result<()> {
+        match self.format {
+            Format::Csv => {
+                let orbits = kepler::orbits(step);
+                for (n, (body, orbit)) in step.bodies.iter().zip(orbits).enumerate() {
+                    write!(
+                        self.writer,
+                        "{},{},{},{},{},{},{},{},{},{}",
+                        step.time,
+                        step.step,
+                        n,
+                        body.mass,
+                        body.position.x,
+                        body.position.y,
+                        body.position.z,
+                        body.velocity.x,
+                        body.velocity.y,
+                        body.velocity.z
+                    )?;
+                    match orbit {
+                        Some(orbit) => write!(
+                            self.writer,
+                            ",{},{},{},",
+                            orbit.semi_major_axis, orbit.eccentricity, orbit.argument_of_periapsis
+                        )?,
+                        None => write!(self.writer, ",,,,")?,
+                    }
+                    if let Some(period) = orbit.and_then(|orbit| orbit.period) {
+                        write!(self.writer, "{}", period)?;
+                    }
+                    writeln!(self.writer)?;
+                }
+            }
+            Format::JsonLines => {
+                serde_json::to_writer(&mut self.writer, &StepRecord::from(step))?;
+                writeln!(self.writer)?;
+            }
+            Format::Binary => {
+                self.writer.write_all(&step.time.to_le_bytes())?;
+                self.writer.write_all(&step.step.to_le_bytes())?;
+                self.writer
+                    .write_all(&(step.bodies.len() as u32).to_le_bytes())?;
+                let orbits = kepler::orbits(step);
+                for (body, orbit) in step.bodies.iter().zip(orbits) {
+                    let values = [
+                        body.mass,
+                        body.position.x,
+                        body.position.y,
+                        body.position.z,
+                        body.velocity.x,
+                        body.velocity.y,
+                        body.velocity.z,
+                        orbit.map_or(f64::NAN, |orbit| orbit.semi_major_axis),
+                        orbit.map_or(f64::NAN, |orbit| orbit.eccentricity),
+                        orbit.map_or(f64::NAN, |orbit| orbit.argument_of_periapsis),
+                        orbit.and_then(|orbit| orbit.period).unwrap_or(f64::NAN),
+                    ];
+                    for value in values {
+                        self.writer.write_all(&value.to_le_bytes())?;
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+
+    pub fn finish(mut self) -> io::Result<W> {
+        self.writer.flush()?;
+        Ok(self.writer)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Body;
+    use macroquad::prelude::*;
+    use std::f64::consts::TAU;
+
+    /// A circular binary of radius 1 and period 2π.
+    fn step() -> Step {
+        Step {
+            time: 1.5,
+            step: 3,
+            ..Step::new(vec![
+                Body::new(dvec3(-0.5, 0.0, 0.0), RED)
+                    .with_mass(0.5)
+                    .with_velocity(dvec3(0.0, -0.5, 0.0)),
+                Body::new(dvec3(0.5, 0.0, 0.0), BLUE)
+                    .with_mass(0.5)
+                    .with_velocity(dvec3(0.0, 0.5, 0.0)),
+            ])
+            .with_gravitational_constant(1.0)
+        }
+    }
+
+    fn export(format: Format) -> Vec<u8> {
+        let mut exporter = Exporter::new(Vec::new(), format).unwrap();
+        exporter.write(&step()).unwrap();
+        exporter.finish().unwrap()
+    }
+
+    #[test]
+    fn test_csv() {
+        assert_eq!(
+            String::from_utf8(export(Format::Csv)).unwrap(),
+            "time,step,body,mass,x,y,z,vx,vy,vz,\
+             semi_major_axis,eccentricity,argument_of_periapsis,period\n\
+             1.5,3,0,0.5,-0.5,0,0,0,-0.5,0,1,0,0,6.283185307179586\n\
+             1.5,3,1,0.5,0.5,0,0,0,0.5,0,1,0,0,6.283185307179586\n"
+        );
+    }
+
+    #[test]
+    fn test_json_lines() {
+        assert_eq!(
+            String::from_utf8(export(Format::JsonLines)).unwrap(),
+            "{\"time\":1.5,\"step\":3,\"bodies\":[\
+             {\"mass\":0.5,\"position\":[-0.5,0.0,0.0],\"velocity\":[0.0,-0.5,0.0],\
+             \"orbit\":{\"semi_major_axis\":1.0,\"eccentricity\":0.0,\
+             \"argument_of_periapsis\":0.0,\"period\":6.283185307179586}},\
+             {\"mass\":0.5,\"position\":[0.5,0.0,0.0],\"velocity\":[0.0,0.5,0.0],\
+             \"orbit\":{\"semi_major_axis\":1.0,\"eccentricity\":0.0,\
+             \"argument_of_periapsis\":0.0,\"period\":6.283185307179586}}]}\n"
+        );
+    }
+
+    #[test]
+    fn test_binary() {
+        let bytes = export(Format::Binary);
+        assert_eq!(&bytes[..4], BINARY_MAGIC);
+        assert_eq!(bytes.len(), 5 + 8 + 4 + 4 + 2 * 11 * 8);
+        assert_eq!(bytes[5..13], 1.5f64.to_le_bytes());
+        assert_eq!(bytes[13..17], 3u32.to_le_bytes());
+        assert_eq!(bytes[17..21], 2u32.to_le_bytes());
+        // The semi-major axis and period of the first body.
+        assert_eq!(bytes[21 + 7 * 8..21 + 8 * 8], 1f64.to_le_bytes());
+        assert_eq!(bytes[21 + 10 * 8..21 + 11 * 8], TAU.to_le_bytes());
+    }
+
+    #[test]
+    fn test_format_from_path() {
+        assert_eq!(Format::from_path("run.jsonl"), Some(Format::JsonLines));
+        assert_eq!(Format::from_path("run.bin"), Some(Format::Binary));
+        assert_eq!(Format::from_path("run"), None);
+    }
+}
@@ -0,0 +1,284 @@
+use crate::options::Options;
+use macroquad::prelude::*;
+use std::fs::{File, OpenOptions};
+use std::io::{self, BufWriter, Seek, SeekFrom, Write};
+use std::path::Path;
+use three_bodies::checkpoint::{Checkpoint, Output};
+use three_bodies::export::{Exporter, Format};
+use three_bodies::scenario::{Scenario, Settings};
+use three_bodies::shooting;
+use three_bodies::simulation::Simulation;
+use three_bodies::sweep::{self, Sweep};
+
+/// Integration steps between two checkpoints unless chosen on the command line.
+const CHECKPOINT_INTERVAL: usize = 1000000;
+/// Phase-space distance a periodic orbit has to come back within unless
+/// chosen on the command line.
+const SHOOTING_TOLERANCE: f64 = 1e-9;
+const SHOOTING_ITERATIONS: usize = 50;
+
+/// Runs `scenario` to the end without opening a window, writing every sample
+/// to the output chosen on the command line, and the crossings of the
+/// surface of section to their own output if one is chosen. A run resumed
+/// from `checkpoint` carries on the output file it was taken with, and
+/// otherwise writes only the samples and crossings after it.
+pub fn run(
+    options: &Options,
+    mut scenario: Scenario,
+    checkpoint: Option<Checkpoint>,
+) -> Result<(), String> {
+    options.apply(&mut scenario);
+    let settings = scenario.settings;
+    let output = options.output.as_deref().unwrap_or("-");
+    let format = options
+        .format
+        .or_else(|| Format::from_path(output))
+        .unwrap_or_default();
+
+    if options.section_output.is_some() && settings.section.is_none() {
+        return Err("--section-output needs a section from --section or the scenario".to_string());
+    }
+    let error = |error: io::Error| format!("Unable to write {}: {}", output, error);
+
+    let reopened = match &checkpoint {
+        Some(checkpoint) => reopen(output, checkpoint.output.as_ref())?,
+        None => None,
+    };
+    let mut exporter = match reopened {
+        Some(writer) => Exporter::append(writer, format),
+        None => Exporter::new(create(output)?, format).map_err(error)?,
+    };
+    // Samples start one interval in, so a new run writes its initial
+    // conditions first.
+    let mut simulation = match checkpoint {
+        Some(checkpoint) => Simulation::resume(checkpoint),
+        None => {
+            exporter.write(&scenario.step).map_err(error)?;
+            Simulation::from_settings(scenario.step, &settings)
+        }
+    };
+
+    let interval = options.checkpoint_interval.unwrap_or(CHECKPOINT_INTERVAL);
+    while simulation.time() < settings.duration() && !simulation.stopped() {
+        if let Some(sample) = simulation.advance()? {
+            exporter.write(&sample).map_err(error)?;
+        }
+        if let Some(path) = &options.checkpoint {
+            if simulation.steps_taken().is_multiple_of(interval) {
+                // A resumed run carries on from the end of what is on disk
+                // now, dropping anything written after the checkpoint.
+                exporter.flush().map_err(error)?;
+                let output = match output {
+                    "-" => None,
+                    output => Some(Output {
+                        path: std::fs::canonicalize(output).map_err(error)?,
+                        length: std::fs::metadata(output).map_err(error)?.len(),
+                    }),
+                };
+                Checkpoint {
+                    output,
+                    ..simulation.checkpoint(settings)
+                }
+                .save(path)?;
+            }
+        }
+    }
+    exporter.finish().map_err(error)?;
+    info!("Maximum drift: {}", simulation.max_drift());
+    if let Some(path) = &options.section_output {
+        let error = |error: io::Error| format!("Unable to write {}: {}", path, error);
+        let format = Format::from_path(path).unwrap_or_default();
+        let mut exporter = Exporter::new(create(path)?, format).map_err(error)?;
+        for crossing in simulation.crossings() {
+            exporter.write(crossing).map_err(error)?;
+        }
+        exporter.finish().map_err(error)?;
+        info!(
+            "Wrote {} crossings of the section to {}",
+            simulation.crossings().len(),
+            path
+        );
+    }
+    if let Some(estimate) = simulation.estimate() {
+        info!("{}", estimate);
+    }
+    Ok(())
+}
+
+/// Runs every member of `sweep` around `scenario` and writes a table of how
+/// each one went to the output chosen on the command line.
+pub fn sweep(options: &Options, mut scenario: Scenario, sweep: &Sweep) -> Result<(), String> {
+    options.apply(&mut scenario);
+    let members = sweep.members(&scenario.step)?;
+    let output = options.output.as_deref().unwrap_or("-");
+    let writer = create(output)?;
+
+    let summaries = sweep::run(&members, &scenario.settings);
+    sweep::write_table(writer, &members, &summaries)
+        .map_err(|error| format!("Unable to write {}: {}", output, error))
+}
+
+/// Refines `scenario` into a periodic orbit close to it with about `period`
+/// and writes it out as a scenario running for whole periods, as JSON if the
+/// output ends in `.json` and as TOML otherwise.
+pub fn find_periodic(options: &Options, mut scenario: Scenario, period: f64) -> Result<(), String> {
+    options.apply(&mut scenario);
+    let settings = scenario.settings;
+    let orbit = shooting::find(
+        &scenario.step,
+        period,
+        settings.time_step,
+        &settings.integrator,
+        options.shooting_tolerance.unwrap_or(SHOOTING_TOLERANCE),
+        SHOOTING_ITERATIONS,
+    )?;
+    info!(
+        "Found a periodic orbit with period {} and residual {:.4e} in {} iterations",
+        orbit.period, orbit.residual, orbit.iterations
+    );
+
+    let steps_per_period = (period / settings.time_step).round().max(1.0);
+    let periods = (settings.duration() / orbit.period).round().max(1.0);
+    let periodic = Scenario {
+        name: format!("{} (periodic, T = {})", scenario.name, orbit.period),
+        settings: Settings {
+            time_step: orbit.period / steps_per_period,
+            steps: (steps_per_period * periods) as usize,
+            ..settings
+        },
+        step: orbit.step,
+    };
+    let output = options.output.as_deref().unwrap_or("-");
+    if output.ends_with(".json") {
+        return periodic.save(output);
+    }
+    let mut writer = create(output)?;
+    write!(writer, "{}", periodic.to_toml())
+        .and_then(|_| writer.flush())
+        .map_err(|error| format!("Unable to write {}: {}", output, error))
+}
+
+/// Opens `output` for writing, `-` being stdout.
+fn create(output: &str) -> Result<Box<dyn Write>, String> {
+    if output == "-" {
+        Ok(Box::new(BufWriter::new(io::stdout().lock())))
+    } else {
+        let file = File::create(output)
+            .map_err(|error| format!("Unable to create {}: {}", output, error))?;
+        Ok(Box::new(BufWriter::new(file)))
+    }
+}
+
+/// Reopens `output` for a run resumed from a checkpoint, cut back to the part
+/// `written` before the checkpoint. Stdout and files that don't exist yet are
+/// left to be created, while any other existing file is never overwritten.
+fn reopen(output: &str, written: Option<&Output>) -> Result<Option<Box<dyn Write>>, String> {
+    if output == "-" || !Path::new(output).exists() {
+        return Ok(None);
+    }
+    let error = |error: io::Error| format!("Unable to reopen {}: {}", output, error);
+    let path = std::fs::canonicalize(output).map_err(error)?;
+    let Some(written) = written.filter(|written| written.path == path) else {
+        return Err(format!(
+            "Not overwriting {}, which isn't the output the checkpoint was taken with",
+            output
+        ));
+    };
+    let mut file = OpenOptions::new().write(true).open(&path).map_err(error)?;
+    if file.metadata().map_err(error)?.len() < written.length {
+        return Err(format!(
+            "{} is shorter than when the checkpoint was taken",
+            output
+        ));
+    }
+    file.set_len(written.length).map_err(error)?;
+    file.seek(SeekFrom::End(0)).map_err(error)?;
+    Ok(Some(Box::new(BufWriter::new(file))))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use three_bodies::presets::{Preset, PRESETS};
+    use three_bodies::replay;
+
+    #[test]
+    fn test_run_writes_each_step_once_and_replays() {
+        let mut scenario = PRESETS[Preset::find("figure-eight").unwrap()].scenario();
+        scenario.settings.steps = 2000;
+        let path = std::env::temp_dir().join("three-bodies-test-run.csv");
+        let options = Options {
+            headless: true,
+            output: Some(path.to_string_lossy().into_owned()),
+            ..Options::default()
+        };
+        run(&options, scenario, None).unwrap();
+
+        let contents = std::fs::read_to_string(&path).unwrap();
+        let replayed = replay::load(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        let steps: Vec<u32> = contents
+            .lines()
+            .skip(1)
+            .map(|line| line.split(',').collect::<Vec<_>>())
+            .filter(|columns| columns[2] == "0")
+            .map(|columns| columns[1].parse().unwrap())
+            .collect();
+        assert!(steps.len() > 1);
+        assert!(
+            steps.windows(2).all(|pair| pair[0] < pair[1]),
+            "{:?}",
+            steps
+        );
+        assert_eq!(replayed.len(), steps.len());
+        assert!(replayed.iter().all(|step| step.bodies.len() == 3));
+    }
+
+    #[test]
+    fn test_resume_carries_on_output() {
+        let mut scenario = PRESETS[Preset::find("figure-eight").unwrap()].scenario();
+        scenario.settings.steps = 2000;
+        let directory = std::env::temp_dir();
+        let path = |name: &str| directory.join(name).to_string_lossy().into_owned();
+        let options = |output: &str| Options {
+            headless: true,
+            output: Some(output.to_string()),
+            checkpoint: Some(path("three-bodies-test-resume.json")),
+            checkpoint_interval: Some(700),
+            ..Options::default()
+        };
+        for format in ["csv", "bin"] {
+            let uninterrupted = path(&format!("three-bodies-test-uninterrupted.{}", format));
+            let interrupted = path(&format!("three-bodies-test-interrupted.{}", format));
+            run(&options(&uninterrupted), scenario.clone(), None).unwrap();
+            // The last checkpoint is taken after 1400 steps, so everything
+            // written after it, and a half-written sample, has to go.
+            run(&options(&interrupted), scenario.clone(), None).unwrap();
+            let mut file = OpenOptions::new().append(true).open(&interrupted).unwrap();
+            file.write_all(b"0.5,").unwrap();
+            let checkpoint = Checkpoint::load(path("three-bodies-test-resume.json")).unwrap();
+            assert!(checkpoint.output.is_some());
+            run(&options(&interrupted), scenario.clone(), Some(checkpoint)).unwrap();
+
+            let expected = std::fs::read(&uninterrupted).unwrap();
+            assert_eq!(std::fs::read(&interrupted).unwrap(), expected, "{}", format);
+
+            // Other existing files are left alone.
+            let checkpoint = Checkpoint::load(path("three-bodies-test-resume.json")).unwrap();
+            assert!(
+                run(&options(&uninterrupted), scenario.clone(), Some(checkpoint))
+                    .unwrap_err()
+                    .starts_with("Not overwriting")
+            );
+            assert_eq!(
+                std::fs::read(&uninterrupted).unwrap(),
+                expected,
+                "{}",
+                format
+            );
+            std::fs::remove_file(&uninterrupted).unwrap();
+            std::fs::remove_file(&interrupted).unwrap();
+        }
+        std::fs::remove_file(path("three-bodies-test-resume.json")).unwrap();
+    }
+}
@@ -0,0 +1,225 @@
+use crate::{Position, Step};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::str::FromStr;
+
+/// Advances every body in a `Step` by one time step.
+pub trait Integrator {
+    fn integrate(&self, step: &mut Step, time_step: f64);
+}
+
+impl<I: Integrator + ?Sized> Integrator for &I {
+    fn integrate(&self, step: &mut Step, time_step: f64) {
+        (**self).integrate(step, time_step);
+    }
+}
+
+/// First order semi-implicit Euler: kick all velocities, then drift positions.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SymplecticEuler;
+
+impl Integrator for SymplecticEuler {
+    fn integrate(&self, step: &mut Step, time_step: f64) {
+        kick(step, step.accelerations(), time_step);
+        drift(step, time_step);
+    }
+}
+
+/// Second order kick-drift-kick Velocity Verlet.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct VelocityVerlet;
+
+impl Integrator for VelocityVerlet {
+    fn integrate(&self, step: &mut Step, time_step: f64) {
+        let half_step = time_step / 2.0;
+        kick(step, step.accelerations(), half_step);
+        drift(step, time_step);
+        kick(step, step.accelerations(), half_step);
+    }
+}
+
+/// Second order drift-kick-drift leapfrog.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Leapfrog;
+
+impl Integrator for Leapfrog {
+    fn integrate(&self, step: &mut Step, time_step: f64) {
+        let half_step = time_step / 2.0;
+        drift(step, half_step);
+        kick(step, step.accelerations(), time_step);
+        drift(step, half_step);
+    }
+}
+
+/// Classic fourth order Runge-Kutta on positions and velocities.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RungeKutta4;
+
+impl Integrator for RungeKutta4 {
+    fn integrate(&self, step: &mut Step, time_step: f64) {
+        let initial = step.clone();
+        let mut probe = initial.clone();
+
+        let derivative = |probe: &Step| -> Vec<(Position, Position)> {
+            let accelerations = probe.accelerations();
+            probe
+                .bodies
+                .iter()
+                .zip(accelerations)
+                .map(|(body, acceleration)| (body.velocity, acceleration))
+                .collect()
+        };
+        let offset = |probe: &mut Step, k: &[(Position, Position)], factor: f64| {
+            for (i, body) in probe.bodies.iter_mut().enumerate() {
+                body.position = initial.bodies[i].position + k[i].0 * factor;
+                body.velocity = initial.bodies[i].velocity + k[i].1 * factor;
+            }
+        };
+
+        let k1 = derivative(&probe);
+        offset(&mut probe, &k1, time_step / 2.0);
+        let k2 = derivative(&probe);
+        offset(&mut probe, &k2, time_step / 2.0);
+        let k3 = derivative(&probe);
+        offset(&mut probe, &k3, time_step);
+        let k4 = derivative(&probe);
+
+        for (i, body) in step.bodies.iter_mut().enumerate() {
+            body.position += (k1[i].0 + 2.0 * k2[i].0 + 2.0 * k3[i].0 + k4[i].0) * time_step / 6.0;
+            body.velocity += (k1[i].1 + 2.0 * k2[i].1 + 2.0 * k3[i].1 + k4[i].1) * time_step / 6.0;
+        }
+    }
+}
+
+fn kick(step: &mut Step, accelerations: Vec<Position>, time_step: f64) {
+    for (body, acceleration) in step.bodies.iter_mut().zip(accelerations) {
+        body.velocity += acceleration * time_step;
+    }
+}
+
+fn drift(step: &mut Step, time_step: f64) {
+    step.bodies
+        .iter_mut()
+        .for_each(|body| body.update(time_step));
+}
+
+/// Selectable integration scheme, e.g. from the command line.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Scheme {
+    #[default]
+    #[serde(rename = "euler")]
+    SymplecticEuler,
+    #[serde(rename = "verlet")]
+    VelocityVerlet,
+    #[serde(rename = "leapfrog")]
+    Leapfrog,
+    #[serde(rename = "rk4")]
+    RungeKutta4,
+}
+
+impl Scheme {
+    pub const ALL: [Scheme; 4] = [
+        Scheme::SymplecticEuler,
+        Scheme::VelocityVerlet,
+        Scheme::Leapfrog,
+        Scheme::RungeKutta4,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Scheme::SymplecticEuler => "euler",
+            Scheme::VelocityVerlet => "verlet",
+            Scheme::Leapfrog => "leapfrog",
+            Scheme::RungeKutta4 => "rk4",
+        }
+    }
+}
+
+impl Integrator for Scheme {
+    fn integrate(&self, step: &mut Step, time_step: f64) {
+        match self {
+            Scheme::SymplecticEuler => SymplecticEuler.integrate(step, time_step),
+            Scheme::VelocityVerlet => VelocityVerlet.integrate(step, time_step),
+            Scheme::Leapfrog => Leapfrog.integrate(step, time_step),
+            Scheme::RungeKutta4 => RungeKutta4.integrate(step, time_step),
+        }
+    }
+}
+
+impl fmt::Display for Scheme {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+impl FromStr for Scheme {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        Scheme::ALL
+            .into_iter()
+            .find(|scheme| scheme.name() == name)
+            .ok_or_else(|| {
+                let names: Vec<_> = Scheme::ALL.iter().map(|scheme| scheme.name()).collect();
+                format!(
+                    "Unknown integrator '{}', expected one of: {}",
+                    name,
+                    names.join(", ")
+                )
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::presets::{Preset, PRESETS};
+    use macroquad::prelude::*;
+
+    #[test]
+    fn test_schemes_agree() {
+        // A third of a period of the figure-eight, far enough for the bodies
+        // to swing round and the schemes to differ.
+        let base = PRESETS[Preset::find("figure-eight").unwrap()]
+            .scenario()
+            .step;
+        let run = |scheme: Scheme, steps: usize| {
+            let mut step = base.clone();
+            for _ in 0..steps {
+                scheme.integrate(&mut step, 2.0 / steps as f64);
+            }
+            step
+        };
+        let reference = run(Scheme::RungeKutta4, 20000);
+        let error = |scheme, steps| {
+            run(scheme, steps)
+                .bodies
+                .iter()
+                .zip(reference.bodies.iter())
+                .map(|(body, expected)| body.position.distance(expected.position))
+                .fold(0.0, f64::max)
+        };
+
+        for scheme in Scheme::ALL {
+            let (order, tolerance) = match scheme {
+                Scheme::SymplecticEuler => (1, 2e-2),
+                Scheme::VelocityVerlet | Scheme::Leapfrog => (2, 2e-4),
+                Scheme::RungeKutta4 => (4, 5e-9),
+            };
+            let coarse = error(scheme, 400);
+            let fine = error(scheme, 800);
+            assert!(coarse < tolerance, "{} is off by {:e}", scheme, coarse);
+            // Halving the time step shrinks the error by 2 to the order.
+            let ratio = coarse / fine;
+            let expected = 2f64.powi(order);
+            assert!(
+                ratio > expected / 1.5 && ratio < expected * 1.5,
+                "{} converges by {} instead of {}",
+                scheme,
+                ratio,
+                expected
+            );
+        }
+        assert!(error(Scheme::SymplecticEuler, 400) > 1e-3);
+    }
+}
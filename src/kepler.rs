@@ -0,0 +1,381 @@
+use crate::{Body, Position, Step};
+use std::f64::consts::{PI, TAU};
+use std::fmt;
+
+/// Most bodies the HUD describes an orbit for when there are more than three.
+const DESCRIBED_BODIES: usize = 6;
+
+/// Below this, an eccentricity or the part of the angular momentum out of the
+/// z axis is taken as zero when placing the periapsis.
+const DEGENERATE: f64 = 1e-12;
+
+/// Keplerian elements of the orbit of two bodies around each other.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Elements {
+    /// Negative for unbound, hyperbolic orbits.
+    pub semi_major_axis: f64,
+    pub eccentricity: f64,
+    /// Angle in radians between the orbital plane and the xy plane.
+    pub inclination: f64,
+    /// Angle in radians from the ascending node to the periapsis, or from
+    /// the x axis for orbits in the xy plane. Zero for circular orbits.
+    pub argument_of_periapsis: f64,
+    /// Time taken by one revolution, for bound orbits.
+    pub period: Option<f64>,
+}
+
+impl Elements {
+    /// Elements of the orbit of `b` around `a`.
+    pub fn new(a: &Body, b: &Body, gravitational_constant: f64) -> Self {
+        Elements::relative(
+            b.position - a.position,
+            b.velocity - a.velocity,
+            gravitational_constant * (a.mass + b.mass),
+        )
+    }
+
+    /// Elements of an orbit with relative position `r` and velocity `v`
+    /// around a gravitational parameter `mu`.
+    fn relative(r: Position, v: Position, mu: f64) -> Self {
+        let angular_momentum = r.cross(v);
+        let energy = 0.5 * v.length_squared() - mu / r.length();
+        let eccentricity = v.cross(angular_momentum) / mu - r.normalize_or_zero();
+        let semi_major_axis = -mu / (2.0 * energy);
+
+        let node = Position::Z.cross(angular_momentum);
+        let argument_of_periapsis = if eccentricity.length() < DEGENERATE {
+            0.0
+        } else if node.length() < DEGENERATE * angular_momentum.length() {
+            let longitude = eccentricity.y.atan2(eccentricity.x);
+            let longitude = if angular_momentum.z < 0.0 {
+                -longitude
+            } else {
+                longitude
+            };
+            longitude.rem_euclid(TAU)
+        } else {
+            let angle = node.angle_between(eccentricity);
+            if eccentricity.z < 0.0 {
+                TAU - angle
+            } else {
+                angle
+            }
+        };
+
+        Elements {
+            semi_major_axis,
+            eccentricity: eccentricity.length(),
+            inclination: angle_to_z(angular_momentum),
+            argument_of_periapsis,
+            period: (energy < 0.0).then(|| TAU * (semi_major_axis.powi(3) / mu).sqrt()),
+        }
+    }
+
+    pub fn is_bound(&self) -> bool {
+        self.period.is_some()
+    }
+}
+
+impl fmt::Display for Elements {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "a = {:.4e}, e = {:.4}, ω = {:.1}°, ",
+            self.semi_major_axis,
+            self.eccentricity,
+            self.argument_of_periapsis.to_degrees()
+        )?;
+        match self.period {
+            Some(period) => write!(f, "P = {:.4e}", period),
+            None => write!(f, "unbound"),
+        }
+    }
+}
+
+/// Three bodies seen as a binary and a third body orbiting its centre of
+/// mass.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Hierarchy {
+    /// Ids of the two bodies of the inner binary.
+    pub inner: (usize, usize),
+    /// Id of the outer body.
+    pub outer: usize,
+    pub inner_orbit: Elements,
+    /// Orbit of the outer body around the centre of mass of the binary.
+    pub outer_orbit: Elements,
+    /// Angle in radians between the inner and outer orbital planes.
+    pub mutual_inclination: f64,
+    /// Whether the outer orbit is far enough out for the triple to last, by
+    /// the criterion of Mardling and Aarseth (2001).
+    pub stable: bool,
+}
+
+impl Hierarchy {
+    /// Finds the binary and outer body of a step with three bodies, taking
+    /// the tightest bound pair as the binary.
+    pub fn find(step: &Step) -> Option<Self> {
+        let [a, b, c] = step.bodies.as_slice() else {
+            return None;
+        };
+        let g = step.gravitational_constant;
+        let (inner, outer, inner_orbit) = [((0, 1), 2), ((0, 2), 1), ((1, 2), 0)]
+            .into_iter()
+            .map(|((i, j), k)| {
+                let bodies = [a, b, c];
+                ((i, j), k, Elements::new(bodies[i], bodies[j], g))
+            })
+            .filter(|(_, _, elements)| elements.is_bound())
+            .min_by(|(_, _, x), (_, _, y)| x.semi_major_axis.total_cmp(&y.semi_major_axis))?;
+
+        let (first, second, third) = (
+            &step.bodies[inner.0],
+            &step.bodies[inner.1],
+            &step.bodies[outer],
+        );
+        let mass = first.mass + second.mass;
+        let position = (first.mass * first.position + second.mass * second.position) / mass;
+        let velocity = (first.mass * first.velocity + second.mass * second.velocity) / mass;
+        let r = third.position - position;
+        let v = third.velocity - velocity;
+        let outer_orbit = Elements::relative(r, v, g * (mass + third.mass));
+
+        let inner_normal =
+            (second.position - first.position).cross(second.velocity - first.velocity);
+        let mutual_inclination = inner_normal.angle_between(r.cross(v));
+        let stable = outer_orbit.is_bound() && {
+            let e = outer_orbit.eccentricity;
+            let q = third.mass / mass;
+            let critical = 2.8
+                * ((1.0 + q) * (1.0 + e) / (1.0 - e).sqrt()).powf(0.4)
+                * (1.0 - 0.3 * mutual_inclination / PI)
+                * inner_orbit.semi_major_axis;
+            outer_orbit.semi_major_axis * (1.0 - e) > critical
+        };
+
+        Some(Hierarchy {
+            inner: (first.id, second.id),
+            outer: third.id,
+            inner_orbit,
+            outer_orbit,
+            mutual_inclination,
+            stable,
+        })
+    }
+
+    /// Lines describing the triple for the HUD, bodies counted from 1.
+    pub fn describe(&self) -> [String; 3] {
+        let kind = if self.stable {
+            "stable hierarchical triple"
+        } else if self.outer_orbit.is_bound() {
+            "unstable triple"
+        } else {
+            "binary and an unbound body"
+        };
+        [
+            format!(
+                "Inner binary {} and {}: {}",
+                self.inner.0 + 1,
+                self.inner.1 + 1,
+                self.inner_orbit
+            ),
+            format!("Outer body {}: {}", self.outer + 1, self.outer_orbit),
+            format!(
+                "Mutual inclination {:.1}°, {}",
+                self.mutual_inclination.to_degrees(),
+                kind
+            ),
+        ]
+    }
+}
+
+/// The body most strongly bound to body `n` of `step`, the one with the
+/// lowest two-body energy, and the orbit of body `n` around it. `None` when
+/// no other body is bound to it.
+pub fn partner(step: &Step, n: usize) -> Option<(usize, Elements)> {
+    let body = &step.bodies[n];
+    let g = step.gravitational_constant;
+    let (partner, _) = step
+        .bodies
+        .iter()
+        .enumerate()
+        .filter(|&(i, _)| i != n)
+        .map(|(i, other)| {
+            let reduced_mass = body.mass * other.mass / (body.mass + other.mass);
+            let energy = 0.5 * reduced_mass * (other.velocity - body.velocity).length_squared()
+                - g * body.mass * other.mass / other.position.distance(body.position);
+            (i, energy)
+        })
+        .filter(|&(_, energy)| energy < 0.0)
+        .min_by(|(_, a), (_, b)| a.total_cmp(b))?;
+    Some((
+        partner,
+        Elements::new(&step.bodies[partner], body, step.gravitational_constant),
+    ))
+}
+
+/// The orbit each body of `step` is part of: the binary for two bodies, the
+/// inner or outer orbit of the hierarchy for three, and the orbit around its
+/// most strongly bound partner for more. `None` for a lone body, for three
+/// that contain no bound pair, or for a body of more with no bound partner.
+pub fn orbits(step: &Step) -> Vec<Option<Elements>> {
+    match step.bodies.as_slice() {
+        [a, b] => vec![Some(Elements::new(a, b, step.gravitational_constant)); 2],
+        [_, _, _] => match Hierarchy::find(step) {
+            Some(hierarchy) => step
+                .bodies
+                .iter()
+                .map(|body| {
+                    Some(if body.id == hierarchy.outer {
+                        hierarchy.outer_orbit
+                    } else {
+                        hierarchy.inner_orbit
+                    })
+                })
+                .collect(),
+            None => vec![None; 3],
+        },
+        bodies => (0..bodies.len())
+            .map(|n| partner(step, n).map(|(_, elements)| elements))
+            .collect(),
+    }
+}
+
+/// Lines describing the orbits of a step for the HUD: the binary or the
+/// hierarchy for two or three bodies, and the orbit of each of the first few
+/// around its most strongly bound partner for more.
+pub fn describe(step: &Step) -> Vec<String> {
+    match step.bodies.as_slice() {
+        [a, b] => vec![format!(
+            "Binary: {}",
+            Elements::new(a, b, step.gravitational_constant)
+        )],
+        [_, _, _] => match Hierarchy::find(step) {
+            Some(hierarchy) => hierarchy.describe().to_vec(),
+            None => vec!["No bound pair".to_string()],
+        },
+        bodies => (0..bodies.len().min(DESCRIBED_BODIES))
+            .filter_map(|n| {
+                let (partner, elements) = partner(step, n)?;
+                Some(format!(
+                    "Body {} around body {}: {}",
+                    step.bodies[n].id + 1,
+                    step.bodies[partner].id + 1,
+                    elements
+                ))
+            })
+            .collect(),
+    }
+}
+
+/// Angle between `vector` and the z axis, 0 for a zero vector.
+fn angle_to_z(vector: Position) -> f64 {
+    if vector == Position::ZERO {
+        0.0
+    } else {
+        vector.angle_between(Position::Z)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::presets::{Preset, PRESETS};
+    use macroquad::prelude::*;
+
+    #[test]
+    fn test_elements() {
+        // Released at apoapsis on the x axis with 80% of the circular speed.
+        let sun = Body::new(DVec3::ZERO, YELLOW).with_mass(1.0);
+        let planet = Body::new(dvec3(-2.0, 0.0, 0.0), BLUE)
+            .with_mass(1e-12)
+            .with_velocity(dvec3(0.0, -0.8 * 0.5f64.sqrt(), 0.0));
+        let elements = Elements::new(&sun, &planet, 1.0);
+        let a = 2.0 / (2.0 - 0.64);
+        assert!(
+            (elements.semi_major_axis - a).abs() < 1e-9,
+            "{:?}",
+            elements
+        );
+        assert!((elements.eccentricity - (2.0 / a - 1.0)).abs() < 1e-9);
+        assert!(elements.argument_of_periapsis.abs() < 1e-9);
+        assert_eq!(elements.inclination, 0.0);
+        assert!((elements.period.unwrap() - TAU * a.powf(1.5)).abs() < 1e-9);
+
+        // The same orbit tilted about the x axis, where both the node and the
+        // periapsis are.
+        let tilted = Body {
+            velocity: dvec3(0.0, -0.8 * 0.5f64.sqrt() * 0.6, -0.8 * 0.5f64.sqrt() * 0.8),
+            ..planet
+        };
+        let tilted = Elements::new(&sun, &tilted, 1.0);
+        assert!((tilted.inclination - 0.8f64.atan2(0.6)).abs() < 1e-9);
+        assert!(tilted.argument_of_periapsis.abs() < 1e-9);
+        assert!((tilted.eccentricity - elements.eccentricity).abs() < 1e-9);
+
+        let escaping = Body {
+            velocity: dvec3(0.0, 1.5, 0.0),
+            ..planet
+        };
+        let elements = Elements::new(&sun, &escaping, 1.0);
+        assert!(elements.semi_major_axis < 0.0 && elements.eccentricity > 1.0);
+        assert_eq!(elements.period, None);
+    }
+
+    #[test]
+    fn test_hierarchy() {
+        let step = PRESETS[Preset::find("inclined").unwrap()].scenario().step;
+        let hierarchy = Hierarchy::find(&step).unwrap();
+        assert!(hierarchy.stable, "{:?}", hierarchy);
+        assert!(hierarchy.mutual_inclination > 0.1);
+        assert!(
+            hierarchy.outer_orbit.semi_major_axis > 2.0 * hierarchy.inner_orbit.semi_major_axis
+        );
+        let per_body = orbits(&step);
+        assert_eq!(per_body[hierarchy.outer], Some(hierarchy.outer_orbit));
+        assert_eq!(per_body[hierarchy.inner.0], Some(hierarchy.inner_orbit));
+
+        let figure_eight = PRESETS[Preset::find("figure-eight").unwrap()]
+            .scenario()
+            .step;
+        let hierarchy = Hierarchy::find(&figure_eight);
+        assert!(
+            hierarchy.is_none_or(|hierarchy| !hierarchy.stable),
+            "{:?}",
+            hierarchy
+        );
+        assert_eq!(orbits(&Step::new(vec![])), vec![]);
+    }
+
+    #[test]
+    fn test_partners() {
+        // Two circular binaries of radius 1 far apart.
+        let binary = |x: f64| {
+            [
+                Body::new(dvec3(x - 0.5, 0.0, 0.0), RED).with_velocity(dvec3(0.0, -0.5, 0.0)),
+                Body::new(dvec3(x + 0.5, 0.0, 0.0), BLUE).with_velocity(dvec3(0.0, 0.5, 0.0)),
+            ]
+        };
+        let mut bodies = binary(0.0).to_vec();
+        bodies.extend(binary(100.0));
+        let step = Step::new(bodies).with_gravitational_constant(0.5);
+
+        assert_eq!(partner(&step, 0).unwrap().0, 1);
+        assert_eq!(partner(&step, 3).unwrap().0, 2);
+        for orbit in orbits(&step) {
+            let orbit = orbit.unwrap();
+            assert!((orbit.semi_major_axis - 1.0).abs() < 1e-9, "{:?}", orbit);
+            assert!(orbit.eccentricity < 1e-9);
+        }
+        let lines = describe(&step);
+        assert_eq!(lines.len(), 4);
+        assert!(lines[2].starts_with("Body 3 around body 4: a = 1.0000e0"));
+
+        // Flung out of its binary fast enough to be bound to nothing.
+        let mut bodies = binary(0.0).to_vec();
+        bodies.extend(binary(100.0));
+        bodies[3].velocity.y = 10.0;
+        let step = Step::new(bodies).with_gravitational_constant(0.5);
+        assert_eq!(partner(&step, 3), None);
+        assert_eq!(orbits(&step)[3], None);
+    }
+}
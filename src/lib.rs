@@ -0,0 +1,693 @@
+use macroquad::prelude::*;
+use std::fmt;
+use std::str::FromStr;
+
+pub mod barnes_hut;
+pub mod camera;
+pub mod checkpoint;
+pub mod collision;
+pub mod diagnostics;
+pub mod encounter;
+pub mod escape;
+pub mod export;
+pub mod integrator;
+pub mod kepler;
+pub mod lyapunov;
+pub mod orbit;
+pub mod playback;
+pub mod plot;
+pub mod presets;
+pub mod replay;
+pub mod scenario;
+pub mod section;
+pub mod shooting;
+pub mod simulation;
+pub mod stream;
+pub mod sweep;
+pub mod time_step;
+pub mod trail;
+
+use collision::Collisions;
+use integrator::Integrator;
+use orbit::OrbitCamera;
+use simulation::Simulation;
+use time_step::TimeStep;
+
+pub const TIME_STEP: f64 = 0.01;
+pub const STEPS: usize = 100000000;
+pub const GRAVITATIONAL_CONSTANT: f64 = 6.67430e-11;
+pub const ANIMATION_FPS: u32 = 30;
+pub const ANIMATION_LENGTH: u32 = 40;
+/// Fewest bodies for which forces are evaluated on several threads, below
+/// which spreading the work costs more than it saves.
+#[cfg(feature = "parallel")]
+const PARALLEL_THRESHOLD: usize = 128;
+
+pub type Position = DVec3;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Body {
+    pub mass: f64,
+    pub position: Position,
+    pub velocity: Position,
+    pub color: Color,
+    /// Size used for collisions and drawing, 0 for a point mass.
+    pub radius: f64,
+    /// Index of the body in the initial conditions, which it keeps when
+    /// others merge into it or bodies before it are merged away.
+    pub id: usize,
+}
+
+impl Body {
+    pub fn new(position: Position, color: Color) -> Self {
+        Body {
+            mass: 1.0,
+            velocity: Position::ZERO,
+            position,
+            color,
+            radius: 0.0,
+            id: 0,
+        }
+    }
+
+    pub fn with_mass(self, mass: f64) -> Self {
+        Body { mass, ..self }
+    }
+
+    pub fn with_velocity(self, velocity: Position) -> Self {
+        Body { velocity, ..self }
+    }
+
+    pub fn with_radius(self, radius: f64) -> Self {
+        Body { radius, ..self }
+    }
+
+    pub fn with_id(self, id: usize) -> Self {
+        Body { id, ..self }
+    }
+
+    /// Where the body with `id` is in `bodies`, if it hasn't been merged
+    /// away. Merging keeps bodies in order, so their ids stay sorted.
+    pub fn find(bodies: &[Body], id: usize) -> Option<usize> {
+        bodies.binary_search_by_key(&id, |body| body.id).ok()
+    }
+
+    fn update(&mut self, time_step: f64) {
+        self.position.x += self.velocity.x * time_step;
+        self.position.y += self.velocity.y * time_step;
+        self.position.z += self.velocity.z * time_step;
+    }
+
+    fn is_finite(&self) -> bool {
+        self.position.is_finite() && self.velocity.is_finite()
+    }
+}
+
+impl fmt::Display for Body {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "({:.04}, {:.04}, {:.04})",
+            self.position.x, self.position.y, self.position.z
+        )
+    }
+}
+
+const COMPONENTS: [char; 3] = ['x', 'y', 'z'];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Quantity {
+    Position,
+    Velocity,
+    Mass,
+}
+
+/// A single number in the initial conditions of a body, written like `x1`,
+/// `vy2` or `m3` with bodies counted from 1.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Parameter {
+    pub quantity: Quantity,
+    /// Index of the component of a position or velocity, 0 for a mass.
+    pub component: usize,
+    /// Id of the body, counted from 0.
+    pub body: usize,
+}
+
+impl Parameter {
+    /// Adds `offset` to the parameter in `step`.
+    pub fn apply(self, step: &mut Step, offset: f64) -> Result<(), String> {
+        let count = step.bodies.len();
+        let body = Body::find(&step.bodies, self.body)
+            .map(|n| &mut step.bodies[n])
+            .ok_or_else(|| {
+                format!(
+                    "Parameter {} refers to a missing body, there are {}",
+                    self, count
+                )
+            })?;
+        match self.quantity {
+            Quantity::Position => body.position[self.component] += offset,
+            Quantity::Velocity => body.velocity[self.component] += offset,
+            Quantity::Mass => body.mass += offset,
+        }
+        Ok(())
+    }
+
+    /// The value of the parameter among `bodies`, unless the body is missing.
+    pub fn value(self, bodies: &[Body]) -> Option<f64> {
+        let body = &bodies[Body::find(bodies, self.body)?];
+        Some(match self.quantity {
+            Quantity::Position => body.position[self.component],
+            Quantity::Velocity => body.velocity[self.component],
+            Quantity::Mass => body.mass,
+        })
+    }
+}
+
+impl fmt::Display for Parameter {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let component = COMPONENTS[self.component];
+        match self.quantity {
+            Quantity::Position => write!(f, "{}{}", component, self.body + 1),
+            Quantity::Velocity => write!(f, "v{}{}", component, self.body + 1),
+            Quantity::Mass => write!(f, "m{}", self.body + 1),
+        }
+    }
+}
+
+impl FromStr for Parameter {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("Invalid parameter '{}', expected e.g. x1, vy2 or m3", name);
+        let (quantity, rest) = match name.strip_prefix('m') {
+            Some(rest) => (Quantity::Mass, rest),
+            None => match name.strip_prefix('v') {
+                Some(rest) => (Quantity::Velocity, rest),
+                None => (Quantity::Position, name),
+            },
+        };
+        let (component, rest) = match quantity {
+            Quantity::Mass => (0, rest),
+            _ => {
+                let mut chars = rest.chars();
+                let component = chars
+                    .next()
+                    .and_then(|c| COMPONENTS.iter().position(|&component| component == c))
+                    .ok_or_else(invalid)?;
+                (component, chars.as_str())
+            }
+        };
+        match rest.parse::<usize>() {
+            Ok(body) if body >= 1 => Ok(Parameter {
+                quantity,
+                component,
+                body: body - 1,
+            }),
+            _ => Err(invalid()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Step {
+    pub time: f64,
+    pub step: u32,
+    pub bodies: Vec<Body>,
+    pub gravitational_constant: f64,
+    /// Plummer softening length, keeping forces finite when bodies get
+    /// closer than this.
+    pub softening: f64,
+    pub collisions: Collisions,
+    /// Evaluates forces with a Barnes-Hut tree with this opening angle when
+    /// set, and by direct summation over every pair otherwise.
+    pub opening_angle: Option<f64>,
+}
+
+impl Step {
+    /// Numbers `bodies` in order, which is how they are referred to from
+    /// then on.
+    pub fn new(bodies: Vec<Body>) -> Self {
+        let bodies = bodies
+            .into_iter()
+            .enumerate()
+            .map(|(id, body)| Body { id, ..body })
+            .collect();
+        Step {
+            time: 0.0,
+            step: 0,
+            bodies,
+            gravitational_constant: GRAVITATIONAL_CONSTANT,
+            softening: 0.0,
+            collisions: Collisions::default(),
+            opening_angle: None,
+        }
+    }
+
+    pub fn with_gravitational_constant(self, gravitational_constant: f64) -> Self {
+        Step {
+            gravitational_constant,
+            ..self
+        }
+    }
+
+    pub fn with_softening(self, softening: f64) -> Self {
+        Step { softening, ..self }
+    }
+
+    pub fn with_collisions(self, collisions: Collisions) -> Self {
+        Step { collisions, ..self }
+    }
+
+    pub fn with_opening_angle(self, opening_angle: Option<f64>) -> Self {
+        Step {
+            opening_angle,
+            ..self
+        }
+    }
+
+    /// Whether every body stays in the xy plane.
+    pub fn is_planar(&self) -> bool {
+        self.bodies
+            .iter()
+            .all(|body| body.position.z == 0.0 && body.velocity.z == 0.0)
+    }
+
+    /// Draws every body projected onto the xy plane at its radius, or as a
+    /// dot sized in units of `pixel_size` if that would be smaller.
+    pub fn draw(&self, pixel_size: f32) {
+        for body in self.bodies.iter() {
+            let position = body.position.truncate().as_vec2();
+            let radius = (body.radius as f32).max(4. * pixel_size);
+            draw_circle(position.x, position.y, radius, body.color);
+        }
+    }
+
+    /// Draws every body as a sphere seen through `camera`, at its radius or
+    /// a few pixels across if that would be smaller.
+    pub fn draw_3d(&self, camera: &OrbitCamera) {
+        for body in self.bodies.iter() {
+            let radius = camera.to_view_length(body.radius.max(4. * camera.pixel_size() as f64));
+            draw_sphere(camera.to_view(body.position), radius, None, body.color);
+        }
+    }
+
+    /// Advances the bodies by `time_step` and resolves collisions, failing
+    /// rather than carrying on once a position or velocity is no longer a
+    /// finite number, which happens when unsoftened bodies get too close.
+    pub fn update(&mut self, integrator: &dyn Integrator, time_step: f64) -> Result<(), String> {
+        integrator.integrate(self, time_step);
+        self.collisions.resolve(self);
+        match self.bodies.iter().find(|body| !body.is_finite()) {
+            Some(body) => Err(format!(
+                "Body {} was flung off to infinity at t = {}, \
+                 try softening or a smaller time step",
+                body.id + 1,
+                self.time
+            )),
+            None => Ok(()),
+        }
+    }
+
+    pub fn next_step(self, time_step: f64) -> Self {
+        Step {
+            time: self.time + time_step,
+            step: self.step + 1,
+            ..self
+        }
+    }
+
+    /// Acceleration of every body caused by the gravity of all the others.
+    pub fn accelerations(&self) -> Vec<Position> {
+        match self.opening_angle {
+            Some(opening_angle) => barnes_hut::accelerations(self, opening_angle),
+            None => self.direct_accelerations(),
+        }
+    }
+
+    /// Accelerations summed directly over every pair of bodies. With the
+    /// `parallel` feature and more than one thread, steps with many bodies
+    /// sum the forces on each body on its own, spread over the threads. That
+    /// visits each pair twice, so it only pays off with several cores.
+    fn direct_accelerations(&self) -> Vec<Position> {
+        #[cfg(feature = "parallel")]
+        if self.bodies.len() >= PARALLEL_THRESHOLD && rayon::current_num_threads() > 1 {
+            use rayon::prelude::*;
+            return (0..self.bodies.len())
+                .into_par_iter()
+                .map(|i| self.direct_acceleration(i))
+                .collect();
+        }
+        self.pairwise_accelerations()
+    }
+
+    /// Each pair is visited once, applying equal and opposite forces along
+    /// the line between the bodies.
+    fn pairwise_accelerations(&self) -> Vec<Position> {
+        let softening_squared = self.softening * self.softening;
+        let mut accelerations = vec![Position::ZERO; self.bodies.len()];
+        for (i, a) in self.bodies.iter().enumerate() {
+            for (j, b) in self.bodies.iter().enumerate().skip(i + 1) {
+                let displacement = b.position - a.position;
+                let distance_squared = displacement.length_squared() + softening_squared;
+                let pull = displacement
+                    * (self.gravitational_constant / (distance_squared * distance_squared.sqrt()));
+                accelerations[i] += pull * b.mass;
+                accelerations[j] -= pull * a.mass;
+            }
+        }
+        accelerations
+    }
+
+    /// Acceleration of body `i` summed directly over all the others in
+    /// order, rounding exactly like `pairwise_accelerations` does for it.
+    ///
+    /// Negating a difference is exact, so each term is the one the pairwise
+    /// loop adds or subtracts, which keeps parallel runs bit for bit the same
+    /// as serial ones whatever the number of threads.
+    #[cfg_attr(not(any(test, feature = "parallel")), allow(dead_code))]
+    fn direct_acceleration(&self, i: usize) -> Position {
+        let softening_squared = self.softening * self.softening;
+        let a = &self.bodies[i];
+        let mut acceleration = Position::ZERO;
+        for (j, b) in self.bodies.iter().enumerate() {
+            if j != i {
+                let displacement = b.position - a.position;
+                let distance_squared = displacement.length_squared() + softening_squared;
+                let pull = displacement
+                    * (self.gravitational_constant / (distance_squared * distance_squared.sqrt()));
+                acceleration += pull * b.mass;
+            }
+        }
+        acceleration
+    }
+}
+
+impl fmt::Display for Step {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Step:")?;
+        for body in self.bodies.iter() {
+            write!(f, " {}", body)?;
+        }
+        write!(f, "")
+    }
+}
+
+/// Integrates `step` until `duration` has passed, keeping a copy of the bodies
+/// every `sample_interval` of simulated time.
+pub fn simulate(
+    step: Step,
+    duration: f64,
+    time_step: TimeStep,
+    sample_interval: f64,
+    integrator: &dyn Integrator,
+) -> Result<Vec<Step>, String> {
+    let mut steps = Vec::<Step>::with_capacity((duration / sample_interval) as usize + 1);
+    let mut simulation = Simulation::new(step, time_step, sample_interval, integrator);
+
+    simulation.run_until(duration, |sample| {
+        steps.push(sample);
+        Ok(())
+    })?;
+    debug!("Maximum drift: {}", simulation.max_drift());
+
+    Ok(steps)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use integrator::Scheme;
+
+    #[test]
+    fn test_parameters() {
+        for name in ["x1", "vy2", "vz3", "m3"] {
+            assert_eq!(name.parse::<Parameter>().unwrap().to_string(), name);
+        }
+        assert_eq!(
+            "vy2".parse(),
+            Ok(Parameter {
+                quantity: Quantity::Velocity,
+                component: 1,
+                body: 1
+            })
+        );
+        for name in ["x0", "w1", "vx", "m"] {
+            assert!(name.parse::<Parameter>().is_err(), "{}", name);
+        }
+    }
+
+    #[test]
+    fn test_simulate() {
+        let initial_step = Step::new(vec![
+            Body::new(dvec3(0.3089693008, 0.4236727692, 0.0), RED),
+            Body::new(dvec3(-0.5, 0.0, 0.0), GREEN),
+            Body::new(dvec3(0.5, 0.0, 0.0), BLUE),
+        ]);
+        let steps = simulate(
+            initial_step,
+            2.5,
+            TimeStep::Fixed(0.5),
+            0.5,
+            &Scheme::SymplecticEuler,
+        )
+        .unwrap();
+
+        assert_eq!(
+            steps,
+            vec![
+                Step {
+                    time: 0.5,
+                    step: 1,
+                    bodies: vec![
+                        Body {
+                            mass: 1.0,
+                            position: dvec3(0.30896930081402885, 0.423672769120293, 0.0),
+                            velocity: dvec3(2.805763660064076e-11, -1.5941407464626257e-10, 0.0),
+                            color: RED,
+                            radius: 0.0,
+                            id: 0,
+                        },
+                        Body {
+                            mass: 1.0,
+                            position: dvec3(-0.49999999996558936, 9.282862773097892e-12, 0.0),
+                            velocity: dvec3(6.882126950562699e-11, 1.8565725546195783e-11, 0.0),
+                            color: GREEN,
+                            radius: 0.0,
+                            id: 1,
+                        },
+                        Body {
+                            mass: 1.0,
+                            position: dvec3(0.4999999999515605, 7.042417455003339e-11, 0.0),
+                            velocity: dvec3(-9.687890610626775e-11, 1.4084834910006679e-10, 0.0),
+                            color: BLUE,
+                            radius: 0.0,
+                            id: 2,
+                        }
+                    ],
+                    gravitational_constant: GRAVITATIONAL_CONSTANT,
+                    softening: 0.0,
+                    collisions: Collisions::PassThrough,
+                    opening_angle: None,
+                },
+                Step {
+                    time: 1.0,
+                    step: 2,
+                    bodies: vec![
+                        Body {
+                            mass: 1.0,
+                            position: dvec3(0.30896930084208646, 0.4236727689608789, 0.0),
+                            velocity: dvec3(5.611527324112885e-11, -3.188281493901134e-10, 0.0),
+                            color: RED,
+                            radius: 0.0,
+                            id: 0,
+                        },
+                        Body {
+                            mass: 1.0,
+                            position: dvec3(-0.4999999998967681, 2.784858832017373e-11, 0.0),
+                            velocity: dvec3(1.3764253902280134e-10, 3.713145109415167e-11, 0.0),
+                            color: GREEN,
+                            radius: 0.0,
+                            id: 1,
+                        },
+                        Body {
+                            mass: 1.0,
+                            position: dvec3(0.49999999985468163, 2.1127252369801421e-10, 0.0),
+                            velocity: dvec3(-1.937578122639302e-10, 2.8169669829596167e-10, 0.0),
+                            color: BLUE,
+                            radius: 0.0,
+                            id: 2,
+                        }
+                    ],
+                    gravitational_constant: GRAVITATIONAL_CONSTANT,
+                    softening: 0.0,
+                    collisions: Collisions::PassThrough,
+                    opening_angle: None,
+                },
+                Step {
+                    time: 1.5,
+                    step: 3,
+                    bodies: vec![
+                        Body {
+                            mass: 1.0,
+                            position: dvec3(0.3089693008841729, 0.4236727687217578, 0.0),
+                            velocity: dvec3(8.417290996131172e-11, -4.782422243291407e-10, 0.0),
+                            color: RED,
+                            radius: 0.0,
+                            id: 0,
+                        },
+                        Body {
+                            mass: 1.0,
+                            position: dvec3(-0.49999999979353615, 5.569717664298761e-11, 0.0),
+                            velocity: dvec3(2.064638085630704e-10, 5.5697176645627765e-11, 0.0),
+                            color: GREEN,
+                            radius: 0.0,
+                            id: 1,
+                        },
+                        Body {
+                            mass: 1.0,
+                            position: dvec3(0.49999999970936326, 4.225450475397706e-10, 0.0),
+                            velocity: dvec3(-2.906367185243821e-10, 4.2254504768351283e-10, 0.0),
+                            color: BLUE,
+                            radius: 0.0,
+                            id: 2,
+                        }
+                    ],
+                    gravitational_constant: GRAVITATIONAL_CONSTANT,
+                    softening: 0.0,
+                    collisions: Collisions::PassThrough,
+                    opening_angle: None,
+                },
+                Step {
+                    time: 2.0,
+                    step: 4,
+                    bodies: vec![
+                        Body {
+                            mass: 1.0,
+                            position: dvec3(0.3089693009402882, 0.42367276840292967, 0.0),
+                            velocity: dvec3(1.1223054680103672e-10, -6.376562995609326e-10, 0.0),
+                            color: RED,
+                            radius: 0.0,
+                            id: 0,
+                        },
+                        Body {
+                            mass: 1.0,
+                            position: dvec3(-0.4999999996558936, 9.28286277441797e-11, 0.0),
+                            velocity: dvec3(2.752850781379816e-10, 7.426290220238417e-11, 0.0),
+                            color: GREEN,
+                            radius: 0.0,
+                            id: 1,
+                        },
+                        Body {
+                            mass: 1.0,
+                            position: dvec3(0.4999999995156055, 7.042417462190449e-10, 0.0),
+                            velocity: dvec3(-3.8751562493901825e-10, 5.633933973585484e-10, 0.0),
+                            color: BLUE,
+                            radius: 0.0,
+                            id: 2,
+                        }
+                    ],
+                    gravitational_constant: GRAVITATIONAL_CONSTANT,
+                    softening: 0.0,
+                    collisions: Collisions::PassThrough,
+                    opening_angle: None,
+                },
+                Step {
+                    time: 2.5,
+                    step: 5,
+                    bodies: vec![
+                        Body {
+                            mass: 1.0,
+                            position: dvec3(0.3089693010104323, 0.42367276800439446, 0.0),
+                            velocity: dvec3(1.402881838001512e-10, -7.970703751830775e-10, 0.0),
+                            color: RED,
+                            radius: 0.0,
+                            id: 0,
+                        },
+                        Body {
+                            mass: 1.0,
+                            position: dvec3(-0.49999999948384044, 1.3924294162727017e-10, 0.0),
+                            velocity: dvec3(3.4410634775908215e-10, 9.282862776618097e-11, 0.0),
+                            color: GREEN,
+                            radius: 0.0,
+                            id: 1,
+                        },
+                        Body {
+                            mass: 1.0,
+                            position: dvec3(0.4999999992734082, 1.056362619927493e-9, 0.0),
+                            velocity: dvec3(-4.843945315592333e-10, 7.042417474168965e-10, 0.0),
+                            color: BLUE,
+                            radius: 0.0,
+                            id: 2,
+                        }
+                    ],
+                    gravitational_constant: GRAVITATIONAL_CONSTANT,
+                    softening: 0.0,
+                    collisions: Collisions::PassThrough,
+                    opening_angle: None,
+                }
+            ]
+        );
+    }
+
+    #[test]
+    fn test_simulate_two_bodies() {
+        let initial_step = Step::new(vec![
+            Body::new(dvec3(-0.5, 0.0, 0.0), RED),
+            Body::new(dvec3(0.5, 0.0, 0.0), BLUE),
+        ]);
+        let steps = simulate(
+            initial_step,
+            9e4,
+            TimeStep::Fixed(1000.0),
+            1e4,
+            &Scheme::SymplecticEuler,
+        )
+        .unwrap();
+
+        assert_eq!(steps.len(), 9);
+        for step in steps.iter() {
+            let [first, second] = step.bodies[..] else {
+                panic!("Expected two bodies in {}", step);
+            };
+            assert!((first.position + second.position).length() < 1e-12);
+            assert!((first.velocity + second.velocity).length() < 1e-12);
+            assert!(first.position.x > -0.5);
+        }
+    }
+
+    #[test]
+    fn test_coincident_bodies() {
+        let initial_step = Step::new(vec![
+            Body::new(dvec3(0.0, 0.0, 0.0), RED),
+            Body::new(dvec3(0.0, 0.0, 0.0), BLUE).with_velocity(dvec3(1.0, 0.0, 0.0)),
+        ])
+        .with_gravitational_constant(1.0);
+
+        let error = simulate(
+            initial_step.clone(),
+            1.0,
+            TimeStep::Fixed(0.1),
+            0.1,
+            &Scheme::VelocityVerlet,
+        )
+        .unwrap_err();
+        assert_eq!(
+            error,
+            "Body 1 was flung off to infinity at t = 0, try softening or a smaller time step"
+        );
+
+        let steps = simulate(
+            initial_step.with_softening(0.1),
+            1.0,
+            TimeStep::Fixed(0.1),
+            0.1,
+            &Scheme::VelocityVerlet,
+        )
+        .unwrap();
+        assert!(steps
+            .iter()
+            .all(|step| step.bodies.iter().all(|body| body.is_finite())));
+        assert!(steps.len() >= 10);
+    }
+}
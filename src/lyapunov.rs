@@ -0,0 +1,254 @@
+use crate::integrator::Integrator;
+use crate::orbit::OrbitCamera;
+use crate::{Body, Position, Step};
+use macroquad::prelude::*;
+use std::fmt;
+
+/// Phase-space distance between the run and its shadow after each
+/// renormalisation, relative to the size of the initial state.
+const SEPARATION: f64 = 1e-9;
+/// Growth of the separation at which the shadow is pulled back in, well
+/// before it stops growing linearly.
+const RENORMALISE_AT: f64 = 1e4;
+/// Initial offset of the overlay, relative to the size of the initial state,
+/// large enough to tell apart on screen after a while.
+const OVERLAY_SEPARATION: f64 = 1e-6;
+const OVERLAY_ALPHA: f32 = 0.5;
+
+/// Estimates the maximal Lyapunov exponent of a run by integrating a shadow
+/// copy that starts a tiny distance away in phase space and is pulled back to
+/// that distance whenever it has drifted far off, adding up the logarithms
+/// of how much it grew in between.
+///
+/// A second copy that is never pulled back shows the divergence on screen.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Lyapunov {
+    pub shadow: Step,
+    /// Starts further away than `shadow` and is left to diverge.
+    pub overlay: Step,
+    /// Distance of `shadow` after each renormalisation.
+    pub separation: f64,
+    /// Sum of the logarithms of the growth before each renormalisation.
+    pub log_growth: f64,
+    /// Logarithm of the growth since the last renormalisation.
+    pub current_growth: f64,
+    pub start: f64,
+    /// Time of the run the shadow has been integrated to.
+    pub time: f64,
+}
+
+impl Lyapunov {
+    /// Starts shadowing `step`, nudging the shadows in a fixed direction of
+    /// phase space that stays in the plane for planar runs.
+    pub fn new(step: &Step) -> Self {
+        let size = phase_norm(&step.bodies);
+        let direction = direction(step);
+        let shadow = |separation: f64| {
+            let mut shadow = step.clone();
+            let factor = separation * size.max(f64::MIN_POSITIVE);
+            for (body, (position, velocity)) in shadow.bodies.iter_mut().zip(direction.iter()) {
+                body.position += *position * factor;
+                body.velocity += *velocity * factor;
+            }
+            shadow
+        };
+        Lyapunov {
+            separation: SEPARATION * size.max(f64::MIN_POSITIVE),
+            shadow: shadow(SEPARATION),
+            overlay: shadow(OVERLAY_SEPARATION),
+            log_growth: 0.0,
+            current_growth: 0.0,
+            start: step.time,
+            time: step.time,
+        }
+    }
+
+    /// Takes the shadows through the same step the run just took to
+    /// `reference`, renormalising the first if it has grown too far.
+    pub fn update(&mut self, reference: &Step, integrator: &dyn Integrator, time_step: f64) {
+        for shadow in [&mut self.shadow, &mut self.overlay] {
+            // A shadow that fails just stops telling anything new.
+            if shadow.update(integrator, time_step).is_ok() {
+                *shadow = std::mem::take(shadow).next_step(time_step);
+            }
+        }
+        self.time = reference.time;
+
+        let Some(distance) = phase_distance(reference, &self.shadow) else {
+            return;
+        };
+        self.current_growth = (distance / self.separation).ln();
+        if distance > RENORMALISE_AT * self.separation {
+            self.log_growth += self.current_growth;
+            self.current_growth = 0.0;
+            let scale = self.separation / distance;
+            for (shadow, body) in self.shadow.bodies.iter_mut().zip(reference.bodies.iter()) {
+                shadow.position = body.position + (shadow.position - body.position) * scale;
+                shadow.velocity = body.velocity + (shadow.velocity - body.velocity) * scale;
+            }
+        }
+    }
+
+    /// Estimate of the maximal Lyapunov exponent so far, in inverse units of
+    /// time, once any time has passed.
+    pub fn exponent(&self) -> Option<f64> {
+        let elapsed = self.time - self.start;
+        (elapsed > 0.0).then(|| (self.log_growth + self.current_growth) / elapsed)
+    }
+
+    /// What a run that has got to `step` publishes with it.
+    pub fn estimate(&self, step: &Step) -> Estimate {
+        Estimate {
+            step: step.step,
+            exponent: self.exponent(),
+            overlay: self.overlay.bodies.clone(),
+        }
+    }
+}
+
+/// The estimate of the Lyapunov exponent at a sample, along with the bodies
+/// of the overlay to show next to it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Estimate {
+    /// Index of the step the estimate was made at.
+    pub step: u32,
+    pub exponent: Option<f64>,
+    pub overlay: Vec<Body>,
+}
+
+impl Estimate {
+    /// Draws the bodies of the overlay as outlines projected onto the xy
+    /// plane, sized like the bodies of the run.
+    pub fn draw(&self, pixel_size: f32) {
+        for body in self.overlay.iter() {
+            let position = body.position.truncate().as_vec2();
+            let radius = (body.radius as f32).max(4. * pixel_size);
+            draw_circle_lines(position.x, position.y, radius, pixel_size, faded(body));
+        }
+    }
+
+    /// Draws the bodies of the overlay as wireframe spheres.
+    pub fn draw_3d(&self, camera: &OrbitCamera) {
+        for body in self.overlay.iter() {
+            let radius = camera.to_view_length(body.radius.max(4. * camera.pixel_size() as f64));
+            draw_sphere_wires(camera.to_view(body.position), radius, None, faded(body));
+        }
+    }
+}
+
+impl fmt::Display for Estimate {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.exponent {
+            Some(exponent) if exponent > 0.0 => write!(
+                f,
+                "Lyapunov exponent: {:.4e} (Lyapunov time {:.4e})",
+                exponent,
+                1.0 / exponent
+            ),
+            Some(exponent) => write!(f, "Lyapunov exponent: {:.4e}", exponent),
+            None => write!(f, "Lyapunov exponent: not known yet"),
+        }
+    }
+}
+
+fn faded(body: &Body) -> Color {
+    Color {
+        a: body.color.a * OVERLAY_ALPHA,
+        ..body.color
+    }
+}
+
+/// Length of the state of all bodies taken as one vector of positions and
+/// velocities.
+fn phase_norm(bodies: &[Body]) -> f64 {
+    bodies
+        .iter()
+        .map(|body| body.position.length_squared() + body.velocity.length_squared())
+        .sum::<f64>()
+        .sqrt()
+}
+
+/// Distance in phase space between two steps, unless they no longer have
+/// the same bodies or it can't be told.
+fn phase_distance(a: &Step, b: &Step) -> Option<f64> {
+    if a.bodies.len() != b.bodies.len() {
+        return None;
+    }
+    let distance = a
+        .bodies
+        .iter()
+        .zip(b.bodies.iter())
+        .map(|(a, b)| {
+            a.position.distance_squared(b.position) + a.velocity.distance_squared(b.velocity)
+        })
+        .sum::<f64>()
+        .sqrt();
+    distance.is_finite().then_some(distance)
+}
+
+/// A unit vector in phase space with every component of about the same size
+/// but differing, spread by the golden ratio.
+fn direction(step: &Step) -> Vec<(Position, Position)> {
+    let planar = step.is_planar();
+    let mut n = 0;
+    let mut component = || {
+        n += 1;
+        (n as f64 * 0.618_033_988_749_895).fract() - 0.5
+    };
+    let mut vector = || {
+        let x = component();
+        let y = component();
+        let z = component();
+        dvec3(x, y, if planar { 0.0 } else { z })
+    };
+    let direction: Vec<_> = step.bodies.iter().map(|_| (vector(), vector())).collect();
+    let norm = direction
+        .iter()
+        .map(|(position, velocity)| position.length_squared() + velocity.length_squared())
+        .sum::<f64>()
+        .sqrt();
+    direction
+        .into_iter()
+        .map(|(position, velocity)| (position / norm, velocity / norm))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::integrator::Scheme;
+    use crate::presets::{Preset, PRESETS};
+    use crate::simulation::Simulation;
+    use crate::time_step::TimeStep;
+
+    fn exponent(preset: &str, duration: f64) -> f64 {
+        let step = PRESETS[Preset::find(preset).unwrap()].scenario().step;
+        let mut simulation = Simulation::new(
+            step,
+            TimeStep::Fixed(1e-3),
+            duration / 10.0,
+            Scheme::RungeKutta4,
+        )
+        .with_lyapunov();
+        simulation.run_until(duration, |_| Ok(())).unwrap();
+        simulation.lyapunov().unwrap().exponent().unwrap()
+    }
+
+    #[test]
+    fn test_start() {
+        let step = PRESETS[0].scenario().step;
+        let lyapunov = Lyapunov::new(&step);
+        let distance = phase_distance(&step, &lyapunov.shadow).unwrap();
+        assert!((distance / lyapunov.separation - 1.0).abs() < 1e-6);
+        assert!(lyapunov.shadow.is_planar());
+        assert_eq!(lyapunov.exponent(), None);
+    }
+
+    #[test]
+    fn test_stable_and_chaotic() {
+        let stable = exponent("figure-eight", 20.0);
+        let chaotic = exponent("pythagorean", 20.0);
+        assert!(stable.abs() < 0.5, "{}", stable);
+        assert!(chaotic > 1.0, "{}", chaotic);
+    }
+}
@@ -1,298 +1,321 @@
 use macroquad::prelude::*;
-use std::fmt;
+use three_bodies::camera::{Camera, CameraMode};
+use three_bodies::checkpoint::Checkpoint;
+use three_bodies::diagnostics::{Diagnostics, Drift};
+use three_bodies::kepler;
+use three_bodies::lyapunov::Estimate;
+use three_bodies::orbit::OrbitCamera;
+use three_bodies::playback::{Playback, HISTORY};
+use three_bodies::plot::{self, Plot, Series};
+use three_bodies::presets::PRESETS;
+use three_bodies::replay;
+use three_bodies::scenario::Scenario;
+use three_bodies::section::SectionRecorder;
+use three_bodies::simulation::Simulation;
+use three_bodies::stream::Stream;
+use three_bodies::trail::Trails;
+use three_bodies::{Body, Step};
 
-const TIME_STEP: f64 = 0.01;
-const STEPS: usize = 100000000;
-const GRAVITATIONAL_CONSTANT: f64 = 6.67430e-11;
-const ANIMATION_FPS: u32 = 30;
-const ANIMATION_LENGTH: u32 = 40;
+mod headless;
+mod menu;
+mod options;
 
-type Position = DVec2;
+use menu::Menu;
+use options::Options;
 
-#[derive(Debug, Clone, Copy, PartialEq)]
-struct Body {
-    mass: f64,
-    position: Position,
-    velocity: Position,
-}
-
-impl Body {
-    fn new(position: Position) -> Self {
-        Body {
-            mass: 1.0,
-            velocity: Position::ZERO,
-            position,
+fn main() {
+    let options = match Options::parse(std::env::args().skip(1)) {
+        Ok(options) => options,
+        Err(error) => exit(&error),
+    };
+    let mut scenario = match &options.scenario {
+        Some(path) => match Scenario::load(path) {
+            Ok(scenario) => scenario,
+            Err(error) => exit(&error),
+        },
+        None => PRESETS[options.preset].scenario(),
+    };
+    let checkpoint = options.resume.as_ref().map(|path| {
+        let checkpoint = Checkpoint::load(path).unwrap_or_else(|error| exit(&error));
+        scenario = Scenario {
+            name: path.clone(),
+            settings: checkpoint.settings,
+            step: checkpoint.step.clone(),
+        };
+        checkpoint
+    });
+    // The scenario or preset only provides the gravitational constant for the
+    // diagnostics of a recording.
+    let recording = options.replay.as_ref().map(|path| {
+        let gravitational_constant = scenario.settings.gravitational_constant;
+        let steps: Vec<Step> = match replay::load(path) {
+            Ok(steps) => steps
+                .into_iter()
+                .map(|step| step.with_gravitational_constant(gravitational_constant))
+                .collect(),
+            Err(error) => exit(&error),
+        };
+        scenario.name = path.clone();
+        scenario.step = steps[0].clone();
+        steps
+    });
+    if let Some(section) = options.section {
+        if let Err(error) = section.check(scenario.step.bodies.len()) {
+            exit(&error);
         }
     }
 
-    fn update(&mut self, time_step: f64) {
-        self.position.x += self.velocity.x * time_step;
-        self.position.y += self.velocity.y * time_step;
-    }
-}
-
-impl fmt::Display for Body {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "({:.04}, {:.04})", self.position.x, self.position.y)
+    if let Some(period) = options.period {
+        if let Err(error) = headless::find_periodic(&options, scenario, period) {
+            exit(&error);
+        }
+    } else if let Some(sweep) = options.sweep() {
+        if let Err(error) = headless::sweep(&options, scenario, &sweep) {
+            exit(&error);
+        }
+    } else if options.headless {
+        if let Err(error) = headless::run(&options, scenario, checkpoint) {
+            exit(&error);
+        }
+    } else {
+        macroquad::Window::new(
+            "Three bodies",
+            run(options, scenario, recording, checkpoint),
+        );
     }
 }
 
-#[derive(Debug, Clone, Copy, PartialEq)]
-struct Step {
-    time: f64,
-    step: u32,
-    bodies: [Body; 3],
+fn exit(error: &str) -> ! {
+    eprintln!("{}", error);
+    std::process::exit(1);
 }
 
-impl Step {
-    fn new(first: Body, second: Body, third: Body) -> Self {
-        Step {
-            time: 0.0,
-            step: 0,
-            bodies: [first, second, third],
-        }
-    }
+async fn run(
+    options: Options,
+    mut scenario: Scenario,
+    mut recording: Option<Vec<Step>>,
+    mut checkpoint: Option<Checkpoint>,
+) {
+    let mut menu = Menu::new(options.preset);
+    let mut show_hud = true;
+    let mut show_trails = true;
+    let mut show_overlay = true;
+    let mut show_section = true;
+    let mut show_phase_space = false;
 
-    fn draw(&self) {
-        draw_circle(
-            self.bodies[0].position.x as f32 * 100.,
-            self.bodies[0].position.y as f32 * 100.,
-            2.,
-            RED,
-        );
-        draw_circle(
-            self.bodies[1].position.x as f32 * 100.,
-            self.bodies[1].position.y as f32 * 100.,
-            2.,
-            GREEN,
+    'simulation: loop {
+        options.apply(&mut scenario);
+        let settings = scenario.settings;
+        debug!(
+            "Simulating {} using {} integrator",
+            scenario.name, settings.integrator
         );
-        draw_circle(
-            self.bodies[2].position.x as f32 * 100.,
-            self.bodies[2].position.y as f32 * 100.,
-            2.,
-            BLUE,
-        );
-    }
-
-    fn update(&mut self, time_step: f64) {
-        self.calculate_step(time_step);
-        self.bodies
-            .iter_mut()
-            .for_each(|body| body.update(time_step));
-    }
 
-    fn next_step(self, time_step: f64) -> Self {
-        Step {
-            time: self.time + time_step,
-            step: self.step + 1,
-            bodies: self.bodies,
-        }
-    }
+        let initial = Diagnostics::new(&scenario.step);
+        let mut source = match recording.take() {
+            Some(steps) => Source::Recording(
+                steps.into_iter().skip(1),
+                settings
+                    .section
+                    .map(|section| SectionRecorder::new(section, &scenario.step)),
+            ),
+            None => {
+                let simulation = match checkpoint.take() {
+                    Some(checkpoint) => Simulation::resume(checkpoint),
+                    None => Simulation::from_settings(scenario.step.clone(), &settings),
+                };
+                Source::Simulation(Stream::from_simulation(
+                    simulation,
+                    settings.animation_fps as usize,
+                ))
+            }
+        };
+        let mut playback =
+            Playback::new(scenario.step.clone()).with_limit(options.history.unwrap_or(HISTORY));
+        let trails = Trails::new(options.trail);
+        let mut camera = Camera::new(CameraMode::AutoFit);
+        let mut orbit = OrbitCamera::new(CameraMode::AutoFit);
+        // Planar runs start out seen from above.
+        let mut three_d = !scenario.step.is_planar();
+        let mut max_drift = Drift::default();
 
-    fn calculate_step(&mut self, time_step: f64) {
-        for i in 0..3 {
-            for j in 0..3 {
-                if i != j {
-                    self.calculate_bodies(i, j, time_step);
+        loop {
+            let input = !playback.captures_input();
+            if input && is_key_pressed(KeyCode::H) {
+                show_hud = !show_hud;
+            }
+            if input && is_key_pressed(KeyCode::T) {
+                show_trails = !show_trails;
+            }
+            if input && is_key_pressed(KeyCode::V) {
+                three_d = !three_d;
+            }
+            if input && is_key_pressed(KeyCode::L) {
+                show_overlay = !show_overlay;
+            }
+            if input && is_key_pressed(KeyCode::S) {
+                show_section = !show_section;
+            }
+            if input && is_key_pressed(KeyCode::P) {
+                show_phase_space = !show_phase_space;
+            }
+            if input {
+                if let Some(choice) = menu.update() {
+                    scenario = PRESETS[choice].scenario();
+                    continue 'simulation;
                 }
             }
-        }
-    }
-
-    fn calculate_bodies(&mut self, i: usize, j: usize, time_step: f64) {
-        let a = &self.bodies[j];
-        let mut b: Body = self.bodies[i];
+            playback.update(|| source.poll());
+            let step = playback.current();
 
-        let dx = a.position.x - b.position.x;
-        let dy: f64 = a.position.y - b.position.y;
-
-        let r: f64 = (dx * dx + dy * dy).sqrt();
-        let force = GRAVITATIONAL_CONSTANT * a.mass * b.mass / r / r;
-        let angle = dy.atan2(dx);
-        let fx = force * angle.cos();
-        let fy = force * angle.sin();
-        b.velocity.x += fx / b.mass * time_step;
-        b.velocity.y += fy / b.mass * time_step;
-
-        self.bodies[i] = b;
-    }
-}
+            clear_background(WHITE);
+            if three_d {
+                orbit.update(step, input);
+                set_camera(&orbit.camera_3d());
+                orbit.draw_grid();
+                if show_trails {
+                    trails.draw_3d(playback.history(), &orbit);
+                }
+                step.draw_3d(&orbit);
+                if let Some(estimate) = source.lyapunov_at(step).filter(|_| show_overlay) {
+                    estimate.draw_3d(&orbit);
+                }
+            } else {
+                camera.update(step, input);
+                set_camera(&camera.camera_2d());
+                if show_trails {
+                    trails.draw(playback.history(), camera.pixel_size());
+                }
+                step.draw(camera.pixel_size());
+                if let Some(estimate) = source.lyapunov_at(step).filter(|_| show_overlay) {
+                    estimate.draw(camera.pixel_size());
+                }
+            }
 
-impl fmt::Display for Step {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "Step:")?;
-        for body in self.bodies.iter() {
-            write!(f, " {}", body)?;
+            set_default_camera();
+            if show_hud {
+                let diagnostics = Diagnostics::new(step);
+                let drift = diagnostics.drift(&initial);
+                max_drift = max_drift.max(drift);
+                let mut notes = kepler::describe(step);
+                notes.extend(source.lyapunov().map(ToString::to_string));
+                diagnostics.draw(&drift, &max_drift, &notes);
+            }
+            let mut panels = 0;
+            if let Some(section) = settings.section.filter(|_| show_section) {
+                // Only crossings up to the step shown, so they appear as the
+                // playback gets to them.
+                let points = source
+                    .crossings_until(step)
+                    .iter()
+                    .filter_map(|crossing| section.point(crossing))
+                    .map(|(x, y)| dvec2(x, y))
+                    .collect();
+                let color = Body::find(&step.bodies, section.surface.body)
+                    .map_or(DARKGRAY, |n| step.bodies[n].color);
+                Plot {
+                    title: &format!("Section {}", section),
+                    x_label: &section.axes.0.to_string(),
+                    y_label: &section.axes.1.to_string(),
+                }
+                .draw(
+                    panels,
+                    &[Series {
+                        points,
+                        color,
+                        joined: false,
+                    }],
+                );
+                panels += 1;
+            }
+            if show_phase_space {
+                let series: Vec<Series> = step
+                    .bodies
+                    .iter()
+                    .map(|body| Series {
+                        points: plot::spread(playback.history(), plot::MAX_POINTS)
+                            .filter_map(|step| {
+                                Body::find(&step.bodies, body.id).map(|n| &step.bodies[n])
+                            })
+                            .map(|body| dvec2(body.position.x, body.velocity.x))
+                            .collect(),
+                        color: body.color,
+                        joined: true,
+                    })
+                    .collect();
+                Plot {
+                    title: "Phase space",
+                    x_label: "x",
+                    y_label: "vx",
+                }
+                .draw(panels, &series);
+            }
+            playback.draw();
+            if let Some(error) = source.error() {
+                draw_text(error, 10., screen_height() - 50., 20., RED);
+            }
+            menu.draw();
+            next_frame().await;
         }
-        write!(f, "")
     }
 }
 
-fn simulate(mut step: Step, count: usize, time_step: f64, steps_per_frame: usize) -> Vec<Step> {
-    let mut steps = Vec::<Step>::with_capacity(count / steps_per_frame);
-
-    for n in 0..count {
-        step.update(time_step);
-        if n % steps_per_frame == 0 {
-            steps.push(step);
-        }
-        step = step.next_step(time_step);
+/// Where the steps that are shown come from.
+enum Source {
+    Simulation(Stream),
+    /// Recorded steps and the crossings of the surface of section found in
+    /// them so far, if one is set.
+    Recording(
+        std::iter::Skip<std::vec::IntoIter<Step>>,
+        Option<SectionRecorder>,
+    ),
+}
 
-        if n % 1000000 == 0 {
-            debug!("Finished step {}", n);
+impl Source {
+    fn poll(&mut self) -> Option<Step> {
+        match self {
+            Source::Simulation(stream) => stream.poll(),
+            Source::Recording(steps, section) => {
+                let step = steps.next()?;
+                if let Some(section) = section.as_mut() {
+                    section.update(&step);
+                }
+                Some(step)
+            }
         }
     }
 
-    steps
-}
-
-#[macroquad::main("Three bodies")]
-async fn main() {
-    let first = Body::new(dvec2(0.3089693008, 0.4236727692));
-    let second = Body::new(dvec2(-0.5, 0.0));
-    let third = Body::new(dvec2(0.5, 0.0));
-
-    let initial_step = Step::new(first, second, third);
-    let steps_per_frame =
-        (STEPS as f64 / (ANIMATION_LENGTH * ANIMATION_FPS) as f64).round() as usize;
-    let steps = simulate(initial_step, STEPS, TIME_STEP, steps_per_frame);
-    debug!("Simulation finished");
-
-    set_camera(&Camera2D::from_display_rect(Rect::new(
-        -100., -100., 200., 200.,
-    )));
+    /// Crossings of the surface of section up to the state `step` holds.
+    fn crossings_until(&self, step: &Step) -> &[Step] {
+        let crossings = match self {
+            Source::Simulation(stream) => stream.crossings(),
+            Source::Recording(_, section) => section
+                .as_ref()
+                .map_or(&[][..], |section| section.crossings()),
+        };
+        let end = crossings.partition_point(|crossing| crossing.step <= step.step);
+        &crossings[..end]
+    }
 
-    loop {
-        for step in steps.iter().chain(steps.iter().rev()) {
-            clear_background(WHITE);
-            step.draw();
-            next_frame().await;
+    /// Estimate of the Lyapunov exponent at the latest sample of a run.
+    fn lyapunov(&self) -> Option<&Estimate> {
+        match self {
+            Source::Simulation(stream) => stream.lyapunov(),
+            Source::Recording(..) => None,
         }
     }
-}
-
-#[cfg(test)]
-mod tests {
-    use super::*;
-
-    #[test]
-    fn test_simulate() {
-        let first = Body::new(dvec2(0.3089693008, 0.4236727692));
-        let second = Body::new(dvec2(-0.5, 0.0));
-        let third = Body::new(dvec2(0.5, 0.0));
 
-        let initial_step = Step::new(first, second, third);
-        let steps = simulate(initial_step, 5, 0.5, 1);
+    /// The estimate of the Lyapunov exponent made at `step`, which only the
+    /// latest sample keeps so the overlay isn't shown while looking back.
+    fn lyapunov_at(&self, step: &Step) -> Option<&Estimate> {
+        self.lyapunov()
+            .filter(|estimate| estimate.step == step.step)
+    }
 
-        assert_eq!(
-            steps,
-            vec![
-                Step {
-                    time: 0.0,
-                    step: 0,
-                    bodies: [
-                        Body {
-                            mass: 1.0,
-                            position: dvec2(0.30896930081402885, 0.423672769120293),
-                            velocity: dvec2(2.8057636600640765e-11, -1.5941407464626255e-10)
-                        },
-                        Body {
-                            mass: 1.0,
-                            position: dvec2(-0.49999999996558936, 9.282862773097892e-12),
-                            velocity: dvec2(6.882126950562697e-11, 1.8565725546195783e-11)
-                        },
-                        Body {
-                            mass: 1.0,
-                            position: dvec2(0.4999999999515605, 7.042417455003339e-11),
-                            velocity: dvec2(-9.687890610626773e-11, 1.4084834910006679e-10)
-                        }
-                    ]
-                },
-                Step {
-                    time: 0.5,
-                    step: 1,
-                    bodies: [
-                        Body {
-                            mass: 1.0,
-                            position: dvec2(0.30896930084208646, 0.4236727689608789),
-                            velocity: dvec2(5.611527324112887e-11, -3.188281493901134e-10)
-                        },
-                        Body {
-                            mass: 1.0,
-                            position: dvec2(-0.4999999998967681, 2.784858832017373e-11),
-                            velocity: dvec2(1.3764253902280134e-10, 3.713145109415168e-11)
-                        },
-                        Body {
-                            mass: 1.0,
-                            position: dvec2(0.49999999985468163, 2.1127252369801421e-10),
-                            velocity: dvec2(-1.937578122639302e-10, 2.8169669829596167e-10)
-                        }
-                    ]
-                },
-                Step {
-                    time: 1.0,
-                    step: 2,
-                    bodies: [
-                        Body {
-                            mass: 1.0,
-                            position: dvec2(0.3089693008841729, 0.4236727687217578),
-                            velocity: dvec2(8.417290996131172e-11, -4.782422243291407e-10)
-                        },
-                        Body {
-                            mass: 1.0,
-                            position: dvec2(-0.49999999979353615, 5.569717664298761e-11),
-                            velocity: dvec2(2.0646380856307043e-10, 5.569717664562777e-11)
-                        },
-                        Body {
-                            mass: 1.0,
-                            position: dvec2(0.49999999970936326, 4.2254504753977065e-10),
-                            velocity: dvec2(-2.906367185243821e-10, 4.225450476835129e-10)
-                        }
-                    ]
-                },
-                Step {
-                    time: 1.5,
-                    step: 3,
-                    bodies: [
-                        Body {
-                            mass: 1.0,
-                            position: dvec2(0.3089693009402882, 0.42367276840292967),
-                            velocity: dvec2(1.1223054680103672e-10, -6.376562995609326e-10)
-                        },
-                        Body {
-                            mass: 1.0,
-                            position: dvec2(-0.4999999996558936, 9.28286277441797e-11),
-                            velocity: dvec2(2.752850781379816e-10, 7.426290220238417e-11)
-                        },
-                        Body {
-                            mass: 1.0,
-                            position: dvec2(0.4999999995156055, 7.042417462190449e-10),
-                            velocity: dvec2(-3.8751562493901825e-10, 5.633933973585484e-10)
-                        }
-                    ]
-                },
-                Step {
-                    time: 2.0,
-                    step: 4,
-                    bodies: [
-                        Body {
-                            mass: 1.0,
-                            position: dvec2(0.3089693010104323, 0.42367276800439446),
-                            velocity: dvec2(1.402881838001512e-10, -7.970703751830775e-10)
-                        },
-                        Body {
-                            mass: 1.0,
-                            position: dvec2(-0.49999999948384044, 1.3924294162727017e-10),
-                            velocity: dvec2(3.4410634775908215e-10, 9.282862776618096e-11)
-                        },
-                        Body {
-                            mass: 1.0,
-                            position: dvec2(0.4999999992734082, 1.0563626199274932e-9),
-                            velocity: dvec2(-4.843945315592333e-10, 7.042417474168966e-10)
-                        }
-                    ]
-                }
-            ]
-        );
+    fn error(&self) -> Option<&str> {
+        match self {
+            Source::Simulation(stream) => stream.error(),
+            Source::Recording(..) => None,
+        }
     }
 }
@@ -0,0 +1,63 @@
+use macroquad::prelude::*;
+use three_bodies::presets::PRESETS;
+
+/// In-app list of presets, opened with `M`.
+pub struct Menu {
+    open: bool,
+    selected: usize,
+}
+
+impl Menu {
+    pub fn new(selected: usize) -> Self {
+        Menu {
+            open: false,
+            selected,
+        }
+    }
+
+    /// Handles keyboard input and returns the index of a newly chosen preset.
+    pub fn update(&mut self) -> Option<usize> {
+        if is_key_pressed(KeyCode::M) {
+            self.open = !self.open;
+        }
+        if !self.open {
+            return None;
+        }
+
+        if is_key_pressed(KeyCode::Up) {
+            self.selected = (self.selected + PRESETS.len() - 1) % PRESETS.len();
+        }
+        if is_key_pressed(KeyCode::Down) {
+            self.selected = (self.selected + 1) % PRESETS.len();
+        }
+        if is_key_pressed(KeyCode::Escape) {
+            self.open = false;
+        }
+        if is_key_pressed(KeyCode::Enter) {
+            self.open = false;
+            return Some(self.selected);
+        }
+        None
+    }
+
+    pub fn draw(&self) {
+        if !self.open {
+            return;
+        }
+
+        let x = screen_width() / 2. - 200.;
+        let y = screen_height() / 2. - PRESETS.len() as f32 * 11.;
+        draw_rectangle(
+            x - 10.,
+            y - 30.,
+            420.,
+            PRESETS.len() as f32 * 22. + 40.,
+            Color::new(1., 1., 1., 0.9),
+        );
+        draw_text("Choose a preset (Enter)", x, y - 8., 20., BLACK);
+        for (n, preset) in PRESETS.iter().enumerate() {
+            let color = if n == self.selected { RED } else { DARKGRAY };
+            draw_text(preset.title, x, y + 18. + n as f32 * 22., 20., color);
+        }
+    }
+}
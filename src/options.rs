@@ -0,0 +1,366 @@
+use three_bodies::collision::Collisions;
+use three_bodies::escape::Escapes;
+use three_bodies::export::Format;
+use three_bodies::integrator::Scheme;
+use three_bodies::presets::Preset;
+use three_bodies::scenario::Scenario;
+use three_bodies::section::Section;
+use three_bodies::sweep::{GridAxis, Perturbation, Sweep};
+use three_bodies::trail::TrailLength;
+
+/// Settings chosen on the command line.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Options {
+    /// Overrides the integrator of the scenario when set.
+    pub scheme: Option<Scheme>,
+    /// Index into `PRESETS` of the initial conditions to start with.
+    pub preset: usize,
+    /// Scenario file to load instead of a preset.
+    pub scenario: Option<String>,
+    /// Enables adaptive time stepping with this tolerance when set.
+    pub tolerance: Option<f64>,
+    /// Overrides the softening length of the scenario when set.
+    pub softening: Option<f64>,
+    /// Logs close encounters nearer than this when set.
+    pub encounter_distance: Option<f64>,
+    /// Overrides the collision policy of the scenario when set.
+    pub collisions: Option<Collisions>,
+    /// Density giving bodies without a radius their size when set.
+    pub density: Option<f64>,
+    /// Switches to Barnes-Hut force evaluation with this opening angle when
+    /// set.
+    pub opening_angle: Option<f64>,
+    /// Estimates the maximal Lyapunov exponent along the run.
+    pub lyapunov: bool,
+    /// Overrides the escape policy of the scenario when set.
+    pub escapes: Option<Escapes>,
+    /// Overrides the surface of section of the scenario when set.
+    pub section: Option<Section>,
+    /// File the crossings of the surface of section are written to in
+    /// headless mode.
+    pub section_output: Option<String>,
+    /// Number of samples kept in each orbit trail.
+    pub trail: TrailLength,
+    /// Most samples kept for looking back in the viewer, a default when not
+    /// set.
+    pub history: Option<usize>,
+    /// Recorded trajectory to play back instead of simulating.
+    pub replay: Option<String>,
+    /// Runs the simulation without a window and writes the samples out.
+    pub headless: bool,
+    /// File the samples are written to in headless mode, `-` for stdout.
+    pub output: Option<String>,
+    /// Format of the headless output, guessed from `output` when not set.
+    pub format: Option<Format>,
+    /// File a checkpoint is written to periodically in headless mode.
+    pub checkpoint: Option<String>,
+    /// Integration steps between two checkpoints.
+    pub checkpoint_interval: Option<usize>,
+    /// Checkpoint to carry on a run from.
+    pub resume: Option<String>,
+    /// Parameters to sweep over a grid of offsets.
+    pub grid: Vec<GridAxis>,
+    /// Number of randomly perturbed runs in an ensemble.
+    pub members: Option<usize>,
+    /// Largest random offsets given to the members of an ensemble.
+    pub perturbation: Perturbation,
+    /// Seed of the random offsets of an ensemble.
+    pub seed: u64,
+    /// Guessed period of a periodic orbit to refine the scenario into.
+    pub period: Option<f64>,
+    /// Phase-space distance within which a refined orbit has to come back
+    /// to its start, a default when not set.
+    pub shooting_tolerance: Option<f64>,
+}
+
+impl Options {
+    pub fn parse(mut args: impl Iterator<Item = String>) -> Result<Self, String> {
+        let mut options = Options::default();
+        while let Some(arg) = args.next() {
+            let mut value = || {
+                args.next()
+                    .ok_or_else(|| format!("Missing value for {}", arg))
+            };
+            match arg.as_str() {
+                "--integrator" => options.scheme = Some(value()?.parse()?),
+                "--preset" => options.preset = Preset::find(&value()?)?,
+                "--scenario" => options.scenario = Some(value()?),
+                "--softening" => {
+                    let softening: f64 = value()?
+                        .parse()
+                        .map_err(|error| format!("Invalid softening: {}", error))?;
+                    if !(softening.is_finite() && softening >= 0.0) {
+                        return Err("Softening must be a finite number, not negative".to_string());
+                    }
+                    options.softening = Some(softening);
+                }
+                "--encounter-distance" => {
+                    let distance: f64 = value()?
+                        .parse()
+                        .map_err(|error| format!("Invalid encounter distance: {}", error))?;
+                    if !(distance.is_finite() && distance > 0.0) {
+                        return Err(
+                            "Encounter distance must be a finite positive number".to_string()
+                        );
+                    }
+                    options.encounter_distance = Some(distance);
+                }
+                "--collisions" => options.collisions = Some(value()?.parse()?),
+                "--density" => {
+                    let density: f64 = value()?
+                        .parse()
+                        .map_err(|error| format!("Invalid density: {}", error))?;
+                    if !(density.is_finite() && density > 0.0) {
+                        return Err("Density must be a finite positive number".to_string());
+                    }
+                    options.density = Some(density);
+                }
+                "--opening-angle" => {
+                    let opening_angle: f64 = value()?
+                        .parse()
+                        .map_err(|error| format!("Invalid opening angle: {}", error))?;
+                    if !(opening_angle.is_finite() && opening_angle > 0.0) {
+                        return Err("Opening angle must be a finite positive number".to_string());
+                    }
+                    options.opening_angle = Some(opening_angle);
+                }
+                "--lyapunov" => options.lyapunov = true,
+                "--escapes" => options.escapes = Some(value()?.parse()?),
+                "--section" => options.section = Some(value()?.parse()?),
+                "--section-output" => options.section_output = Some(value()?),
+                "--trail" => options.trail = value()?.parse()?,
+                "--history" => {
+                    let history: usize = value()?
+                        .parse()
+                        .map_err(|error| format!("Invalid history length: {}", error))?;
+                    if history < 4 {
+                        return Err("History must keep at least 4 samples".to_string());
+                    }
+                    options.history = Some(history);
+                }
+                "--replay" => options.replay = Some(value()?),
+                "--headless" => options.headless = true,
+                "--output" => options.output = Some(value()?),
+                "--format" => options.format = Some(value()?.parse()?),
+                "--checkpoint" => options.checkpoint = Some(value()?),
+                "--checkpoint-interval" => {
+                    let interval: usize = value()?
+                        .parse()
+                        .map_err(|error| format!("Invalid checkpoint interval: {}", error))?;
+                    if interval == 0 {
+                        return Err("Checkpoint interval must be at least 1".to_string());
+                    }
+                    options.checkpoint_interval = Some(interval);
+                }
+                "--resume" => options.resume = Some(value()?),
+                "--sweep" => options.grid.push(value()?.parse()?),
+                "--members" => {
+                    let members: usize = value()?
+                        .parse()
+                        .map_err(|error| format!("Invalid number of members: {}", error))?;
+                    if members == 0 {
+                        return Err("An ensemble needs at least one member".to_string());
+                    }
+                    options.members = Some(members);
+                }
+                "--perturb" => options.perturbation.set(&value()?)?,
+                "--seed" => {
+                    options.seed = value()?
+                        .parse()
+                        .map_err(|error| format!("Invalid seed: {}", error))?
+                }
+                "--tolerance" => {
+                    let tolerance: f64 = value()?
+                        .parse()
+                        .map_err(|error| format!("Invalid tolerance: {}", error))?;
+                    if !(tolerance.is_finite() && tolerance > 0.0) {
+                        return Err("Tolerance must be a finite positive number".to_string());
+                    }
+                    options.tolerance = Some(tolerance);
+                }
+                "--find-periodic" => {
+                    let period: f64 = value()?
+                        .parse()
+                        .map_err(|error| format!("Invalid period: {}", error))?;
+                    if !(period.is_finite() && period > 0.0) {
+                        return Err("Period must be a finite positive number".to_string());
+                    }
+                    options.period = Some(period);
+                }
+                "--shooting-tolerance" => {
+                    let tolerance: f64 = value()?
+                        .parse()
+                        .map_err(|error| format!("Invalid shooting tolerance: {}", error))?;
+                    if !(tolerance.is_finite() && tolerance > 0.0) {
+                        return Err(
+                            "Shooting tolerance must be a finite positive number".to_string()
+                        );
+                    }
+                    options.shooting_tolerance = Some(tolerance);
+                }
+                _ => return Err(format!("Unknown argument '{}'", arg)),
+            }
+        }
+        if options.headless && options.replay.is_some() {
+            return Err("--replay can't be used in headless mode".to_string());
+        }
+        if !options.headless && options.checkpoint.is_some() {
+            return Err("--checkpoint can only be used in headless mode".to_string());
+        }
+        if !options.headless && options.section_output.is_some() {
+            return Err("--section-output can only be used in headless mode".to_string());
+        }
+        if !options.grid.is_empty() || options.members.is_some() {
+            if !options.grid.is_empty() && options.members.is_some() {
+                return Err("--sweep can't be combined with --members".to_string());
+            }
+            if options.headless
+                || options.replay.is_some()
+                || options.resume.is_some()
+                || options.checkpoint.is_some()
+                || options.format.is_some()
+            {
+                return Err(
+                    "Sweeps can't be combined with --headless, --replay, --resume, \
+                            --checkpoint or --format"
+                        .to_string(),
+                );
+            }
+        }
+        if options.period.is_some() {
+            if options.headless
+                || options.replay.is_some()
+                || options.resume.is_some()
+                || options.checkpoint.is_some()
+                || options.format.is_some()
+                || !options.grid.is_empty()
+                || options.members.is_some()
+            {
+                return Err(
+                    "--find-periodic can't be combined with --headless, --replay, --resume, \
+                     --checkpoint, --format or sweeps"
+                        .to_string(),
+                );
+            }
+            if options.tolerance.is_some() {
+                return Err(
+                    "--find-periodic integrates with fixed steps and can't use --tolerance"
+                        .to_string(),
+                );
+            }
+        } else if options.shooting_tolerance.is_some() {
+            return Err("--shooting-tolerance only applies to --find-periodic".to_string());
+        }
+        if options.members.is_some() && options.perturbation == Perturbation::default() {
+            return Err("--members needs --perturb to vary the members".to_string());
+        }
+        if options.members.is_none()
+            && (options.perturbation != Perturbation::default() || options.seed != 0)
+        {
+            return Err("--perturb and --seed only apply to --members".to_string());
+        }
+        if options.resume.is_some() {
+            if options.replay.is_some() || options.scenario.is_some() {
+                return Err("--resume can't be combined with --replay or --scenario".to_string());
+            }
+            if options.scheme.is_some()
+                || options.tolerance.is_some()
+                || options.softening.is_some()
+                || options.collisions.is_some()
+                || options.density.is_some()
+                || options.opening_angle.is_some()
+                || options.lyapunov
+                || options.escapes.is_some()
+            {
+                return Err("--resume takes the physics settings from the checkpoint".to_string());
+            }
+            if options.section.is_some() {
+                return Err("--resume records the section stored in the checkpoint".to_string());
+            }
+        }
+        Ok(options)
+    }
+
+    /// The sweep or ensemble asked for on the command line, if any.
+    pub fn sweep(&self) -> Option<Sweep> {
+        match self.members {
+            Some(members) => Some(Sweep::Random {
+                members,
+                perturbation: self.perturbation,
+                seed: self.seed,
+            }),
+            None if !self.grid.is_empty() => Some(Sweep::Grid(self.grid.clone())),
+            None => None,
+        }
+    }
+
+    /// Applies the settings given on the command line on top of those of
+    /// `scenario`.
+    pub fn apply(&self, scenario: &mut Scenario) {
+        let settings = &mut scenario.settings;
+        if let Some(scheme) = self.scheme {
+            settings.integrator = scheme;
+        }
+        if self.tolerance.is_some() {
+            settings.tolerance = self.tolerance;
+        }
+        if let Some(softening) = self.softening {
+            settings.softening = softening;
+            scenario.step.softening = softening;
+        }
+        if self.encounter_distance.is_some() {
+            settings.encounter_distance = self.encounter_distance;
+        }
+        if let Some(escapes) = self.escapes {
+            settings.escapes = escapes;
+        }
+        if self.section.is_some() {
+            settings.section = self.section;
+        }
+        if let Some(collisions) = self.collisions {
+            settings.collisions = collisions;
+            scenario.step.collisions = collisions;
+        }
+        if self.opening_angle.is_some() {
+            settings.opening_angle = self.opening_angle;
+            scenario.step.opening_angle = self.opening_angle;
+        }
+        if let Some(density) = self.density {
+            scenario.set_density(density);
+        }
+        if self.lyapunov {
+            scenario.settings.lyapunov = true;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &[&str]) -> Result<Options, String> {
+        Options::parse(args.iter().map(|arg| arg.to_string()))
+    }
+
+    #[test]
+    fn test_non_finite_numbers() {
+        for flag in [
+            "--tolerance",
+            "--softening",
+            "--density",
+            "--opening-angle",
+            "--encounter-distance",
+            "--shooting-tolerance",
+        ] {
+            for value in ["NaN", "inf", "-1"] {
+                assert!(parse(&[flag, value]).is_err(), "{} {}", flag, value);
+            }
+        }
+        assert_eq!(parse(&["--softening", "0"]).unwrap().softening, Some(0.0));
+        assert_eq!(
+            parse(&["--tolerance", "0.01"]).unwrap().tolerance,
+            Some(0.01)
+        );
+        assert!(parse(&["--find-periodic", "inf"]).is_err());
+    }
+}
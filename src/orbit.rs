@@ -0,0 +1,194 @@
+use crate::camera::CameraMode;
+use crate::diagnostics::Diagnostics;
+use crate::{Body, Position, Step};
+use macroquad::prelude::*;
+use std::f32::consts::FRAC_PI_2;
+
+/// Distance of the eye from the target in view units. Zooming scales the
+/// scene instead of moving the eye, which keeps it between the near and far
+/// planes whatever the units of the simulation.
+const EYE_DISTANCE: f32 = 10.;
+/// Vertical field of view in radians.
+const FIELD_OF_VIEW: f32 = 0.8;
+/// Part of the screen kept free around the bodies when fitting them in view.
+const FIT_MARGIN: f64 = 0.8;
+/// How quickly automatic camera moves catch up, per frame.
+const SMOOTHING: f64 = 0.1;
+const ZOOM_FACTOR: f64 = 1.1;
+/// Radians turned per pixel dragged.
+const ROTATION_SPEED: f32 = 0.01;
+/// Lines on each side of the centre of the reference grid.
+const GRID_LINES: i32 = 5;
+
+/// A 3D camera that orbits around the bodies: dragging turns it around its
+/// target and scrolling zooms. It follows the bodies like the 2D [`Camera`].
+///
+/// [`Camera`]: crate::camera::Camera
+pub struct OrbitCamera {
+    pub mode: CameraMode,
+    target: Position,
+    /// Angle around the z axis.
+    yaw: f32,
+    /// Angle above the xy plane.
+    pitch: f32,
+    /// View units per simulation unit of length.
+    scale: f64,
+    drag: Option<Vec2>,
+}
+
+impl OrbitCamera {
+    pub fn new(mode: CameraMode) -> Self {
+        OrbitCamera {
+            mode,
+            target: Position::ZERO,
+            yaw: -FRAC_PI_2,
+            pitch: 0.5,
+            scale: 1.,
+            drag: None,
+        }
+    }
+
+    /// Moves the camera to follow `step`, first handling mouse and keyboard
+    /// input if `input` is set.
+    pub fn update(&mut self, step: &Step, input: bool) {
+        if input {
+            self.handle_input(step);
+        }
+
+        match self.mode {
+            CameraMode::Free => (),
+            CameraMode::AutoFit => {
+                if let Some((target, scale)) = fit(step) {
+                    self.target = self.target.lerp(target, SMOOTHING);
+                    self.scale *= (scale / self.scale).powf(SMOOTHING);
+                }
+            }
+            CameraMode::CentreOfMass => {
+                self.target = Diagnostics::new(step).centre_of_mass;
+            }
+            CameraMode::Body(id) => match Body::find(&step.bodies, id) {
+                Some(n) => self.target = step.bodies[n].position,
+                None => self.mode = CameraMode::Free,
+            },
+        }
+    }
+
+    fn handle_input(&mut self, step: &Step) {
+        if is_key_pressed(KeyCode::F) {
+            self.mode = CameraMode::AutoFit;
+        }
+        if is_key_pressed(KeyCode::C) {
+            self.mode = CameraMode::CentreOfMass;
+        }
+        if is_key_pressed(KeyCode::B) {
+            self.mode = self.mode.next_body(step);
+        }
+
+        let (_, wheel) = mouse_wheel();
+        if wheel != 0. {
+            self.scale *= ZOOM_FACTOR.powf(wheel.signum() as f64);
+            if self.mode == CameraMode::AutoFit {
+                self.mode = CameraMode::Free;
+            }
+        }
+
+        let mouse: Vec2 = mouse_position().into();
+        if is_mouse_button_down(MouseButton::Left) {
+            if let Some(from) = self.drag {
+                let moved = (mouse - from) * ROTATION_SPEED;
+                self.yaw -= moved.x;
+                self.pitch = (self.pitch + moved.y).clamp(-FRAC_PI_2 + 0.01, FRAC_PI_2 - 0.01);
+            }
+            self.drag = Some(mouse);
+        } else {
+            self.drag = None;
+        }
+    }
+
+    /// Simulation units of length covered by one screen pixel at the target.
+    pub fn pixel_size(&self) -> f32 {
+        (view_height() as f64 / screen_height() as f64 / self.scale) as f32
+    }
+
+    /// Position in view units of a point in the simulation.
+    pub fn to_view(&self, position: Position) -> Vec3 {
+        ((position - self.target) * self.scale).as_vec3()
+    }
+
+    /// Length in view units of a length in the simulation.
+    pub fn to_view_length(&self, length: f64) -> f32 {
+        (length * self.scale) as f32
+    }
+
+    pub fn camera_3d(&self) -> Camera3D {
+        let (sin_yaw, cos_yaw) = self.yaw.sin_cos();
+        let (sin_pitch, cos_pitch) = self.pitch.sin_cos();
+        Camera3D {
+            position: EYE_DISTANCE * vec3(cos_pitch * cos_yaw, cos_pitch * sin_yaw, sin_pitch),
+            target: Vec3::ZERO,
+            up: Vec3::Z,
+            fovy: FIELD_OF_VIEW,
+            ..Default::default()
+        }
+    }
+
+    /// Draws a square grid in the xy plane through the target, with a
+    /// spacing that is a power of ten in simulation units.
+    pub fn draw_grid(&self) {
+        let visible = view_height() as f64 / self.scale;
+        let spacing = 10f64.powf((visible / GRID_LINES as f64).log10().floor());
+        let origin = ((self.target.truncate() / spacing).round() * spacing).extend(self.target.z);
+        let extent = GRID_LINES as f64 * spacing;
+        for n in -GRID_LINES..=GRID_LINES {
+            let offset = n as f64 * spacing;
+            for (from, to) in [
+                (dvec3(offset, -extent, 0.), dvec3(offset, extent, 0.)),
+                (dvec3(-extent, offset, 0.), dvec3(extent, offset, 0.)),
+            ] {
+                draw_line_3d(
+                    self.to_view(origin + from),
+                    self.to_view(origin + to),
+                    LIGHTGRAY,
+                );
+            }
+        }
+    }
+}
+
+/// Height in view units of what is visible at the distance of the target.
+fn view_height() -> f32 {
+    2. * EYE_DISTANCE * (FIELD_OF_VIEW / 2.).tan()
+}
+
+/// Target and scale that fit all bodies in view from any direction.
+fn fit(step: &Step) -> Option<(Position, f64)> {
+    let mut bodies = step.bodies.iter().map(|body| body.position);
+    let first = bodies.next()?;
+    let (min, max) = bodies.fold((first, first), |(min, max), position| {
+        (min.min(position), max.max(position))
+    });
+    let radius = ((max - min).length() / 2.).max(1e-9);
+    Some((
+        (min + max) / 2.,
+        FIT_MARGIN * view_height() as f64 / 2. / radius,
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Body;
+
+    #[test]
+    fn test_fit() {
+        let step = Step::new(vec![
+            Body::new(dvec3(-1.0, 0.0, 2.0), RED),
+            Body::new(dvec3(1.0, 0.0, 0.0), GREEN),
+        ]);
+
+        let (target, scale) = fit(&step).unwrap();
+        assert_eq!(target, dvec3(0.0, 0.0, 1.0));
+        assert!((scale * 2f64.sqrt() - FIT_MARGIN * view_height() as f64 / 2.).abs() < 1e-12);
+        assert_eq!(fit(&Step::new(vec![])), None);
+    }
+}
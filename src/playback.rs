@@ -0,0 +1,368 @@
+use crate::Step;
+use macroquad::prelude::*;
+
+const MIN_SPEED: f64 = 1. / 16.;
+const MAX_SPEED: f64 = 64.;
+/// Most samples taken from the simulation in a single frame while jumping ahead.
+const MAX_SAMPLES_PER_FRAME: usize = 10000;
+const TIMELINE_MARGIN: f32 = 10.;
+const TIMELINE_HEIGHT: f32 = 10.;
+/// Most samples kept for looking back unless chosen on the command line.
+pub const HISTORY: usize = 100000;
+
+/// Keeps the sampled steps that have been shown and controls which one is
+/// shown next: pausing, stepping, changing speed and direction, and jumping to
+/// a simulation time either typed in or picked on the timeline.
+///
+/// Once there are more than `limit` steps, every other one is dropped from
+/// the older half, so the whole run stays on the timeline in less detail the
+/// further back it goes.
+pub struct Playback {
+    history: Vec<Step>,
+    limit: usize,
+    /// Index into `history` of the shown step, fractional at slow speeds.
+    cursor: f64,
+    /// Samples advanced per frame.
+    speed: f64,
+    reversed: bool,
+    paused: bool,
+    /// Simulation time to jump to once the simulation has reached it.
+    target: Option<f64>,
+    /// Simulation time being typed in after pressing `J`.
+    prompt: Option<String>,
+    scrubbing: bool,
+}
+
+impl Playback {
+    pub fn new(initial: Step) -> Self {
+        Playback {
+            history: vec![initial],
+            limit: HISTORY,
+            cursor: 0.,
+            speed: 1.,
+            reversed: false,
+            paused: false,
+            target: None,
+            prompt: None,
+            scrubbing: false,
+        }
+    }
+
+    /// Keeps at most about `limit` steps, which has to be at least 4.
+    pub fn with_limit(self, limit: usize) -> Self {
+        Playback { limit, ..self }
+    }
+
+    /// The step to show this frame.
+    pub fn current(&self) -> &Step {
+        &self.history[self.index()]
+    }
+
+    /// Every step up to and including the current one.
+    pub fn history(&self) -> &[Step] {
+        &self.history[..=self.index()]
+    }
+
+    /// Whether input is being used by the timeline or the time prompt and
+    /// should be left alone by everything else.
+    pub fn captures_input(&self) -> bool {
+        self.prompt.is_some() || self.scrubbing || timeline().contains(mouse_position().into())
+    }
+
+    /// Handles keyboard and mouse input and moves on to the step to show next,
+    /// taking new samples from `next_sample` as needed.
+    pub fn update(&mut self, mut next_sample: impl FnMut() -> Option<Step>) {
+        if self.prompt.is_some() {
+            self.update_prompt();
+        } else {
+            self.handle_keys();
+        }
+        self.handle_timeline();
+
+        if let Some(target) = self.target {
+            self.fetch(&mut next_sample, |history| {
+                history.last().is_some_and(|step| step.time >= target)
+            });
+            self.seek(target);
+            if self.last().time >= target {
+                self.target = None;
+            }
+        } else if !self.paused {
+            self.advance(self.speed, &mut next_sample);
+        }
+        self.thin();
+    }
+
+    /// Moves `frames` samples along in the current direction.
+    fn advance(&mut self, frames: f64, next_sample: &mut impl FnMut() -> Option<Step>) {
+        if self.reversed {
+            self.cursor = (self.cursor - frames).max(0.);
+        } else {
+            let cursor = self.cursor + frames;
+            self.fetch(next_sample, |history| history.len() > cursor as usize);
+            self.cursor = if (cursor as usize) < self.history.len() {
+                cursor
+            } else {
+                (self.history.len() - 1) as f64
+            };
+        }
+    }
+
+    /// Takes samples from `next_sample` until `done` or none are ready.
+    fn fetch(
+        &mut self,
+        next_sample: &mut impl FnMut() -> Option<Step>,
+        done: impl Fn(&[Step]) -> bool,
+    ) {
+        for _ in 0..MAX_SAMPLES_PER_FRAME {
+            if done(&self.history) {
+                return;
+            }
+            match next_sample() {
+                Some(sample) => self.history.push(sample),
+                None => return,
+            }
+        }
+    }
+
+    /// Drops every other step from the older half of the history, keeping
+    /// the first, until there are no more than `limit`.
+    fn thin(&mut self) {
+        while self.history.len() > self.limit {
+            let older = self.history.len() / 2;
+            let mut index = 0;
+            self.history.retain(|_| {
+                let keep = index >= older || index % 2 == 0;
+                index += 1;
+                keep
+            });
+            self.cursor = if self.cursor < older as f64 {
+                (self.cursor / 2.).floor()
+            } else {
+                self.cursor - (older / 2) as f64
+            };
+        }
+    }
+
+    /// Moves to the first sample at or after `time`, or the last one if the
+    /// simulation hasn't got that far yet.
+    fn seek(&mut self, time: f64) {
+        let index = self.history.partition_point(|step| step.time < time);
+        self.cursor = index.min(self.history.len() - 1) as f64;
+    }
+
+    fn index(&self) -> usize {
+        self.cursor as usize
+    }
+
+    fn last(&self) -> &Step {
+        self.history.last().expect("History is never empty")
+    }
+
+    fn handle_keys(&mut self) {
+        if is_key_pressed(KeyCode::Space) {
+            self.paused = !self.paused;
+        }
+        if is_key_pressed(KeyCode::R) {
+            self.reversed = !self.reversed;
+        }
+        if is_key_pressed(KeyCode::Equal) || is_key_pressed(KeyCode::KpAdd) {
+            self.speed = (self.speed * 2.).min(MAX_SPEED);
+        }
+        if is_key_pressed(KeyCode::Minus) || is_key_pressed(KeyCode::KpSubtract) {
+            self.speed = (self.speed / 2.).max(MIN_SPEED);
+        }
+        if is_key_pressed(KeyCode::Right) {
+            self.paused = true;
+            self.cursor = (self.cursor.floor() + 1.).min((self.history.len() - 1) as f64);
+        }
+        if is_key_pressed(KeyCode::Left) {
+            self.paused = true;
+            self.cursor = (self.cursor.floor() - 1.).max(0.);
+        }
+        if is_key_pressed(KeyCode::J) {
+            self.prompt = Some(String::new());
+            // Drop the `j` that opened the prompt.
+            while get_char_pressed().is_some() {}
+        }
+    }
+
+    fn update_prompt(&mut self) {
+        let Some(prompt) = self.prompt.as_mut() else {
+            return;
+        };
+        while let Some(character) = get_char_pressed() {
+            if character.is_ascii_digit() || ".eE-+".contains(character) {
+                prompt.push(character);
+            }
+        }
+        if is_key_pressed(KeyCode::Backspace) {
+            prompt.pop();
+        }
+        if is_key_pressed(KeyCode::Escape) {
+            self.prompt = None;
+        } else if is_key_pressed(KeyCode::Enter) {
+            if let Ok(time) = prompt.parse::<f64>() {
+                self.target = Some(time);
+            }
+            self.prompt = None;
+        }
+    }
+
+    fn handle_timeline(&mut self) {
+        let timeline = timeline();
+        let mouse: Vec2 = mouse_position().into();
+        if is_mouse_button_pressed(MouseButton::Left) && timeline.contains(mouse) {
+            self.scrubbing = true;
+        }
+        if !is_mouse_button_down(MouseButton::Left) {
+            self.scrubbing = false;
+        }
+        if self.scrubbing {
+            let fraction = ((mouse.x - timeline.x) / timeline.w).clamp(0., 1.) as f64;
+            let start = self.history[0].time;
+            self.target = None;
+            self.seek(start + fraction * (self.last().time - start));
+        }
+    }
+
+    /// Draws the timeline and playback status at the bottom of the screen.
+    pub fn draw(&self) {
+        let timeline = timeline();
+        let start = self.history[0].time;
+        let span = self.last().time - start;
+        let fraction = if span > 0. {
+            ((self.current().time - start) / span) as f32
+        } else {
+            0.
+        };
+
+        draw_rectangle(timeline.x, timeline.y, timeline.w, timeline.h, LIGHTGRAY);
+        draw_rectangle(
+            timeline.x,
+            timeline.y,
+            timeline.w * fraction,
+            timeline.h,
+            GRAY,
+        );
+        draw_rectangle(
+            timeline.x + timeline.w * fraction - 2.,
+            timeline.y - 3.,
+            4.,
+            timeline.h + 6.,
+            DARKGRAY,
+        );
+
+        let mut status = format!(
+            "t = {:.4} / {:.4}   step {}   speed x{}",
+            self.current().time,
+            self.last().time,
+            self.current().step,
+            self.speed
+        );
+        if self.reversed {
+            status.push_str("   reversed");
+        }
+        if self.paused {
+            status.push_str("   paused");
+        }
+        if let Some(target) = self.target {
+            status.push_str(&format!("   jumping to t = {}", target));
+        }
+        if let Some(prompt) = &self.prompt {
+            status = format!("Jump to time: {}_", prompt);
+        }
+        draw_text(&status, timeline.x, timeline.y - 8., 18., DARKGRAY);
+    }
+}
+
+/// Screen area of the timeline.
+fn timeline() -> Rect {
+    Rect::new(
+        TIMELINE_MARGIN,
+        screen_height() - TIMELINE_MARGIN - TIMELINE_HEIGHT,
+        screen_width() - 2. * TIMELINE_MARGIN,
+        TIMELINE_HEIGHT,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Body;
+
+    fn samples() -> impl FnMut() -> Option<Step> {
+        let mut steps = (1..).map(|n| Step {
+            time: n as f64 * 0.5,
+            step: n,
+            ..Step::new(vec![Body::new(dvec3(n as f64, 0., 0.0), RED)])
+        });
+        move || steps.next()
+    }
+
+    #[test]
+    fn test_advance_and_reverse() {
+        let mut next_sample = samples();
+        let mut playback = Playback::new(Step::new(vec![Body::new(dvec3(0., 0., 0.0), RED)]));
+
+        playback.advance(1., &mut next_sample);
+        playback.advance(0.5, &mut next_sample);
+        assert_eq!(playback.current().step, 1);
+        playback.advance(0.5, &mut next_sample);
+        assert_eq!(playback.current().step, 2);
+        assert_eq!(playback.history().len(), 3);
+
+        playback.reversed = true;
+        playback.advance(4., &mut next_sample);
+        assert_eq!(playback.current().step, 0);
+        assert_eq!(playback.history.len(), 3);
+    }
+
+    #[test]
+    fn test_thin() {
+        let mut next_sample = samples();
+        let mut playback =
+            Playback::new(Step::new(vec![Body::new(dvec3(0., 0., 0.0), RED)])).with_limit(10);
+
+        playback.advance(11., &mut next_sample);
+        assert_eq!(playback.current().step, 11);
+        playback.thin();
+        // Steps 1, 3 and 5 are dropped from the older half.
+        let steps: Vec<u32> = playback.history.iter().map(|step| step.step).collect();
+        assert_eq!(steps, [0, 2, 4, 6, 7, 8, 9, 10, 11]);
+        assert_eq!(playback.current().step, 11);
+
+        playback.cursor = 2.;
+        for _ in 0..100 {
+            playback.advance(1., &mut next_sample);
+            playback.thin();
+            assert!(playback.history.len() <= 10);
+        }
+        assert_eq!(playback.history[0].step, 0);
+        // Moving through the thinned out steps skips some of them.
+        assert_eq!(playback.current().step, 105);
+        assert_eq!(playback.last().step, 105);
+        assert!(playback
+            .history
+            .windows(2)
+            .all(|pair| pair[0].step < pair[1].step));
+    }
+
+    #[test]
+    fn test_seek() {
+        let mut next_sample = samples();
+        let mut playback = Playback::new(Step::new(vec![Body::new(dvec3(0., 0., 0.0), RED)]));
+
+        playback.fetch(&mut next_sample, |history| {
+            history.last().is_some_and(|step| step.time >= 3.)
+        });
+        assert_eq!(playback.last().time, 3.);
+
+        playback.seek(1.2);
+        assert_eq!(playback.current().time, 1.5);
+        playback.seek(10.);
+        assert_eq!(playback.current().time, 3.);
+        playback.seek(-1.);
+        assert_eq!(playback.current().time, 0.);
+    }
+}
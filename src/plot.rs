@@ -0,0 +1,164 @@
+use macroquad::prelude::*;
+
+/// Largest size of a panel in pixels.
+const MAX_SIZE: f32 = 300.;
+const MARGIN: f32 = 10.;
+/// Space kept free at the bottom of the screen for the playback controls.
+const BOTTOM: f32 = 70.;
+const FONT_SIZE: f32 = 16.;
+/// Most points a series built from a long history should have.
+pub const MAX_POINTS: usize = 2000;
+
+/// Points drawn in a plot in one colour, either on their own or joined up.
+pub struct Series {
+    pub points: Vec<DVec2>,
+    pub color: Color,
+    pub joined: bool,
+}
+
+/// At most `count` of `items`, spread evenly over them and always ending
+/// with the last, so a series drawn from them costs the same however many
+/// there are.
+pub fn spread<T>(items: &[T], count: usize) -> impl DoubleEndedIterator<Item = &T> {
+    let stride = items.len().div_ceil(count.max(1)).max(1);
+    items.iter().rev().step_by(stride).rev()
+}
+
+/// Maps values onto a rectangle on the screen, with y going up.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Scale {
+    min: DVec2,
+    max: DVec2,
+    rect: Rect,
+}
+
+impl Scale {
+    /// Fits the range of `points` into `rect` with a little room around
+    /// them, giving a range of 1 to values that don't change.
+    pub fn fit<'a>(points: impl IntoIterator<Item = &'a DVec2>, rect: Rect) -> Option<Self> {
+        let (min, max) = points.into_iter().filter(|point| point.is_finite()).fold(
+            None,
+            |bounds: Option<(DVec2, DVec2)>, &point| {
+                Some(bounds.map_or((point, point), |(min, max)| {
+                    (min.min(point), max.max(point))
+                }))
+            },
+        )?;
+        let range = max - min;
+        let padding = DVec2::select(range.cmpgt(DVec2::ZERO), range * 0.05, DVec2::splat(0.5));
+        Some(Scale {
+            min: min - padding,
+            max: max + padding,
+            rect,
+        })
+    }
+
+    /// Screen position of `point`.
+    pub fn to_screen(&self, point: DVec2) -> Vec2 {
+        let fraction = ((point - self.min) / (self.max - self.min)).as_vec2();
+        vec2(
+            self.rect.x + fraction.x * self.rect.w,
+            self.rect.y + (1. - fraction.y) * self.rect.h,
+        )
+    }
+}
+
+/// A panel down the right side of the screen plotting one quantity against
+/// another, scaled to fit everything in it.
+pub struct Plot<'a> {
+    pub title: &'a str,
+    pub x_label: &'a str,
+    pub y_label: &'a str,
+}
+
+impl Plot<'_> {
+    /// Draws the plot as the `n`th panel from the top.
+    pub fn draw(&self, n: usize, series: &[Series]) {
+        let size = MAX_SIZE
+            .min(screen_width() * 0.3)
+            .min((screen_height() - BOTTOM) / 2. - MARGIN - FONT_SIZE);
+        let rect = Rect::new(
+            screen_width() - size - MARGIN,
+            MARGIN + FONT_SIZE + n as f32 * (size + 2. * MARGIN + FONT_SIZE),
+            size,
+            size,
+        );
+        draw_rectangle(rect.x, rect.y, rect.w, rect.h, Color::new(1., 1., 1., 0.8));
+        draw_rectangle_lines(rect.x, rect.y, rect.w, rect.h, 1., GRAY);
+        draw_text(self.title, rect.x, rect.y - 4., FONT_SIZE, DARKGRAY);
+
+        let points = series.iter().flat_map(|series| series.points.iter());
+        let Some(scale) = Scale::fit(points, rect) else {
+            draw_text(
+                "Nothing yet",
+                rect.x + 8.,
+                rect.y + rect.h / 2.,
+                FONT_SIZE,
+                GRAY,
+            );
+            return;
+        };
+        for series in series {
+            let points: Vec<Vec2> = series
+                .points
+                .iter()
+                .map(|&point| scale.to_screen(point))
+                .collect();
+            if series.joined {
+                for pair in points.windows(2) {
+                    draw_line(pair[0].x, pair[0].y, pair[1].x, pair[1].y, 1., series.color);
+                }
+            } else {
+                for point in points {
+                    draw_circle(point.x, point.y, 1.5, series.color);
+                }
+            }
+        }
+
+        let bottom = rect.y + rect.h - 4.;
+        draw_text(
+            &format!("{} {:.3} .. {:.3}", self.x_label, scale.min.x, scale.max.x),
+            rect.x + 4.,
+            bottom,
+            FONT_SIZE,
+            DARKGRAY,
+        );
+        draw_text(
+            &format!("{} {:.3} .. {:.3}", self.y_label, scale.min.y, scale.max.y),
+            rect.x + 4.,
+            rect.y + FONT_SIZE,
+            FONT_SIZE,
+            DARKGRAY,
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_scale() {
+        let rect = Rect::new(10., 20., 100., 200.);
+        let points = [dvec2(0.0, 1.0), dvec2(10.0, 1.0), dvec2(f64::NAN, 0.0)];
+        let scale = Scale::fit(&points, rect).unwrap();
+        assert_eq!(scale.to_screen(dvec2(5.0, 1.0)), vec2(60., 120.));
+        let left = scale.to_screen(points[0]);
+        assert!(left.x > 10. && left.x < 20.);
+        // Up is towards the top of the screen.
+        assert!(scale.to_screen(dvec2(5.0, 1.4)).y < 120.);
+
+        assert_eq!(Scale::fit(&[], rect), None);
+    }
+
+    #[test]
+    fn test_spread() {
+        let items: Vec<usize> = (0..10).collect();
+        let spread_out = |count| spread(&items, count).copied().collect::<Vec<_>>();
+        assert_eq!(spread_out(20), items);
+        assert_eq!(spread_out(5), [1, 3, 5, 7, 9]);
+        assert_eq!(spread_out(3), [1, 5, 9]);
+        assert_eq!(spread_out(1), [9]);
+        assert_eq!(spread(&[] as &[usize], 3).count(), 0);
+    }
+}
@@ -0,0 +1,317 @@
+use crate::scenario::{Scenario, Settings};
+use crate::{Body, GRAVITATIONAL_CONSTANT, STEPS, TIME_STEP};
+use macroquad::prelude::*;
+use std::f64::consts::{FRAC_PI_2, PI};
+
+/// A named set of initial conditions together with the settings it is meant
+/// to be simulated with.
+pub struct Preset {
+    pub name: &'static str,
+    pub title: &'static str,
+    pub gravitational_constant: f64,
+    pub time_step: f64,
+    pub duration: f64,
+    bodies: fn() -> Vec<Body>,
+}
+
+impl Preset {
+    pub fn scenario(&self) -> Scenario {
+        let settings = Settings {
+            time_step: self.time_step,
+            steps: (self.duration / self.time_step).round() as usize,
+            gravitational_constant: self.gravitational_constant,
+            ..Settings::default()
+        };
+        Scenario::new(self.title, settings, (self.bodies)())
+    }
+
+    pub fn find(name: &str) -> Result<usize, String> {
+        PRESETS
+            .iter()
+            .position(|preset| preset.name == name)
+            .ok_or_else(|| {
+                let names: Vec<_> = PRESETS.iter().map(|preset| preset.name).collect();
+                format!(
+                    "Unknown preset '{}', expected one of: {}",
+                    name,
+                    names.join(", ")
+                )
+            })
+    }
+}
+
+pub const PRESETS: [Preset; 14] = [
+    Preset {
+        name: "at-rest",
+        title: "Three bodies starting at rest",
+        gravitational_constant: GRAVITATIONAL_CONSTANT,
+        time_step: TIME_STEP,
+        duration: STEPS as f64 * TIME_STEP,
+        bodies: at_rest,
+    },
+    Preset {
+        name: "figure-eight",
+        title: "Chenciner-Montgomery figure-eight",
+        gravitational_constant: 1.0,
+        time_step: 1e-4,
+        duration: 3.0 * 6.32591398,
+        bodies: figure_eight,
+    },
+    Preset {
+        name: "lagrange",
+        title: "Lagrange equilateral triangle",
+        gravitational_constant: 1.0,
+        time_step: 1e-3,
+        duration: 24.8,
+        bodies: lagrange,
+    },
+    Preset {
+        name: "euler",
+        title: "Euler collinear",
+        gravitational_constant: 1.0,
+        time_step: 1e-3,
+        duration: 16.9,
+        bodies: euler,
+    },
+    Preset {
+        name: "broucke-a1",
+        title: "Broucke A1",
+        gravitational_constant: 1.0,
+        time_step: 1e-4,
+        duration: 3.0 * 6.2813,
+        bodies: broucke_a1,
+    },
+    Preset {
+        name: "broucke-a2",
+        title: "Broucke A2",
+        gravitational_constant: 1.0,
+        time_step: 1e-4,
+        duration: 3.0 * 7.7022,
+        bodies: broucke_a2,
+    },
+    Preset {
+        name: "butterfly",
+        title: "Suvakov-Dmitrasinovic butterfly I",
+        gravitational_constant: 1.0,
+        time_step: 1e-4,
+        duration: 3.0 * 6.2356,
+        bodies: || suvakov(0.306892758965492, 0.125506782829762),
+    },
+    Preset {
+        name: "moth",
+        title: "Suvakov-Dmitrasinovic moth I",
+        gravitational_constant: 1.0,
+        time_step: 1e-4,
+        duration: 2.0 * 14.8939,
+        bodies: || suvakov(0.464445237398184, 0.396059973403921),
+    },
+    Preset {
+        name: "bumblebee",
+        title: "Suvakov-Dmitrasinovic bumblebee",
+        gravitational_constant: 1.0,
+        time_step: 1e-5,
+        duration: 63.5345,
+        bodies: || suvakov(0.184278506469727, 0.587188195800781),
+    },
+    Preset {
+        name: "dragonfly",
+        title: "Suvakov-Dmitrasinovic dragonfly",
+        gravitational_constant: 1.0,
+        time_step: 1e-5,
+        duration: 21.2710,
+        bodies: || suvakov(0.080584285736084, 0.588836087036132),
+    },
+    Preset {
+        name: "yin-yang",
+        title: "Suvakov-Dmitrasinovic yin-yang Ia",
+        gravitational_constant: 1.0,
+        time_step: 1e-4,
+        duration: 17.3284,
+        bodies: || suvakov(0.513938054919243, 0.304736003875733),
+    },
+    Preset {
+        name: "pythagorean",
+        title: "Pythagorean 3-4-5 problem",
+        gravitational_constant: 1.0,
+        time_step: 1e-5,
+        duration: 70.0,
+        bodies: pythagorean,
+    },
+    Preset {
+        name: "sun-earth-moon",
+        title: "Sun, Earth and Moon (AU, days, solar masses)",
+        gravitational_constant: 2.959122082855911e-4,
+        time_step: 0.01,
+        duration: 365.25,
+        bodies: sun_earth_moon,
+    },
+    Preset {
+        name: "inclined",
+        title: "Hierarchical triple with an inclined outer orbit",
+        gravitational_constant: 1.0,
+        time_step: 1e-3,
+        duration: 200.0,
+        bodies: inclined,
+    },
+];
+
+fn at_rest() -> Vec<Body> {
+    vec![
+        Body::new(dvec3(0.3089693008, 0.4236727692, 0.0), RED),
+        Body::new(dvec3(-0.5, 0.0, 0.0), GREEN),
+        Body::new(dvec3(0.5, 0.0, 0.0), BLUE),
+    ]
+}
+
+fn figure_eight() -> Vec<Body> {
+    let position = dvec3(-0.97000436, 0.24308753, 0.0);
+    let velocity = dvec3(-0.93240737, -0.86473146, 0.0);
+    vec![
+        Body::new(position, RED).with_velocity(-velocity / 2.0),
+        Body::new(-position, GREEN).with_velocity(-velocity / 2.0),
+        Body::new(DVec3::ZERO, BLUE).with_velocity(velocity),
+    ]
+}
+
+/// Equal masses on the unit circle, circling their centre of mass.
+fn lagrange() -> Vec<Body> {
+    let speed = (1.0 / 3.0f64.sqrt()).sqrt();
+    [RED, GREEN, BLUE]
+        .into_iter()
+        .enumerate()
+        .map(|(i, color)| {
+            let direction = DVec2::from_angle(FRAC_PI_2 + i as f64 * 2.0 / 3.0 * PI);
+            Body::new(direction.extend(0.0), color)
+                .with_velocity((direction.perp() * speed).extend(0.0))
+        })
+        .collect()
+}
+
+/// Equal masses on a line, the outer two circling the one in the middle.
+fn euler() -> Vec<Body> {
+    let speed = 1.25f64.sqrt();
+    vec![
+        Body::new(dvec3(-1.0, 0.0, 0.0), RED).with_velocity(dvec3(0.0, -speed, 0.0)),
+        Body::new(DVec3::ZERO, GREEN),
+        Body::new(dvec3(1.0, 0.0, 0.0), BLUE).with_velocity(dvec3(0.0, speed, 0.0)),
+    ]
+}
+
+fn broucke_a1() -> Vec<Body> {
+    broucke([
+        (-0.9892620043, 1.9169244185),
+        (2.2096177241, 0.1910268738),
+        (-1.2203557197, -2.1079512924),
+    ])
+}
+
+fn broucke_a2() -> Vec<Body> {
+    broucke([
+        (0.3361300950, 1.5324315370),
+        (0.7699893804, -0.6287350978),
+        (-1.1061194753, -0.9036964391),
+    ])
+}
+
+/// Bodies starting on the x axis moving along the y axis, given as `(x, vy)`.
+fn broucke(bodies: [(f64, f64); 3]) -> Vec<Body> {
+    bodies
+        .into_iter()
+        .zip([RED, GREEN, BLUE])
+        .map(|((x, vy), color)| {
+            Body::new(dvec3(x, 0.0, 0.0), color).with_velocity(dvec3(0.0, vy, 0.0))
+        })
+        .collect()
+}
+
+/// The isosceles collinear configuration used by Suvakov and Dmitrasinovic,
+/// parameterised by the velocity `(p1, p2)` of the outer bodies.
+fn suvakov(p1: f64, p2: f64) -> Vec<Body> {
+    let velocity = dvec3(p1, p2, 0.0);
+    vec![
+        Body::new(dvec3(-1.0, 0.0, 0.0), RED).with_velocity(velocity),
+        Body::new(dvec3(1.0, 0.0, 0.0), GREEN).with_velocity(velocity),
+        Body::new(DVec3::ZERO, BLUE).with_velocity(-2.0 * velocity),
+    ]
+}
+
+fn pythagorean() -> Vec<Body> {
+    vec![
+        Body::new(dvec3(1.0, 3.0, 0.0), RED).with_mass(3.0),
+        Body::new(dvec3(-2.0, -1.0, 0.0), GREEN).with_mass(4.0),
+        Body::new(dvec3(1.0, -1.0, 0.0), BLUE).with_mass(5.0),
+    ]
+}
+
+fn sun_earth_moon() -> Vec<Body> {
+    let earth_speed = 0.01720209895;
+    let moon_distance = 0.00256955529;
+    let moon_speed = 0.000591;
+    vec![
+        Body::new(DVec3::ZERO, ORANGE),
+        Body::new(dvec3(1.0, 0.0, 0.0), BLUE)
+            .with_mass(3.00348959632e-6)
+            .with_velocity(dvec3(0.0, earth_speed, 0.0)),
+        Body::new(dvec3(1.0 + moon_distance, 0.0, 0.0), GRAY)
+            .with_mass(3.69430370e-8)
+            .with_velocity(dvec3(0.0, earth_speed + moon_speed, 0.0)),
+    ]
+}
+
+/// A circular binary in the xy plane and a lighter third body on a circular
+/// orbit around it tilted by 60 degrees, at rest about their centre of mass.
+fn inclined() -> Vec<Body> {
+    let inclination = PI / 3.0;
+    let binary_speed = 0.5f64.sqrt();
+    let outer_speed = (2.5f64 / 5.0).sqrt();
+    let mut bodies = vec![
+        Body::new(dvec3(-0.5, 0.0, 0.0), RED).with_velocity(dvec3(0.0, -binary_speed, 0.0)),
+        Body::new(dvec3(0.5, 0.0, 0.0), GREEN).with_velocity(dvec3(0.0, binary_speed, 0.0)),
+        Body::new(dvec3(5.0, 0.0, 0.0), BLUE)
+            .with_mass(0.5)
+            .with_velocity(outer_speed * dvec3(0.0, inclination.cos(), inclination.sin())),
+    ];
+    let mass: f64 = bodies.iter().map(|body| body.mass).sum();
+    let (centre, momentum) =
+        bodies
+            .iter()
+            .fold((DVec3::ZERO, DVec3::ZERO), |(centre, momentum), body| {
+                (
+                    centre + body.mass * body.position,
+                    momentum + body.mass * body.velocity,
+                )
+            });
+    for body in bodies.iter_mut() {
+        body.position -= centre / mass;
+        body.velocity -= momentum / mass;
+    }
+    bodies
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::diagnostics::Diagnostics;
+
+    #[test]
+    fn test_presets_have_zero_momentum() {
+        for preset in PRESETS
+            .iter()
+            .filter(|preset| preset.name != "sun-earth-moon")
+        {
+            let diagnostics = Diagnostics::new(&preset.scenario().step);
+            assert!(
+                diagnostics.momentum.length() < 1e-8,
+                "{} has momentum {}",
+                preset.name,
+                diagnostics.momentum
+            );
+        }
+    }
+
+    #[test]
+    fn test_find() {
+        assert_eq!(Preset::find("figure-eight"), Ok(1));
+        assert!(Preset::find("figure-nine").is_err());
+    }
+}
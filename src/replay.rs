@@ -0,0 +1,394 @@
+use crate::export::{StepRecord, BINARY_MAGIC, BINARY_VERSION, CSV_HEADER, CSV_STATE_COLUMNS};
+use crate::scenario::COLORS;
+use crate::{Body, Position, Step};
+use macroquad::prelude::*;
+use std::path::Path;
+
+/// Reads a recorded trajectory: any of the formats written in headless mode,
+/// or a generic CSV with a time column followed by an x and a y column for
+/// each body.
+pub fn load(path: impl AsRef<Path>) -> Result<Vec<Step>, String> {
+    let path = path.as_ref();
+    let contents = std::fs::read(path)
+        .map_err(|error| format!("Unable to read {}: {}", path.display(), error))?;
+    let steps = if contents.starts_with(BINARY_MAGIC) {
+        from_binary(&contents)
+    } else {
+        match std::str::from_utf8(&contents) {
+            Ok(text) if path.extension().is_some_and(|extension| extension == "csv") => {
+                from_csv(text)
+            }
+            Ok(text) => from_json_lines(text),
+            Err(_) => Err("File is neither text nor a binary trajectory".to_string()),
+        }
+    };
+    steps.map_err(|error| format!("Invalid trajectory {}: {}", path.display(), error))
+}
+
+pub fn from_json_lines(contents: &str) -> Result<Vec<Step>, String> {
+    let mut steps = Vec::new();
+    for (n, line) in contents.lines().enumerate() {
+        if line.trim().is_empty() {
+            continue;
+        }
+        let record: StepRecord =
+            serde_json::from_str(line).map_err(|error| format!("Line {}: {}", n + 1, error))?;
+        let bodies = record
+            .bodies
+            .iter()
+            .enumerate()
+            .map(|(i, body)| {
+                Body::new(body.position.into(), COLORS[i % COLORS.len()])
+                    .with_mass(body.mass)
+                    .with_velocity(body.velocity.into())
+            })
+            .collect();
+        steps.push(Step {
+            time: record.time,
+            step: record.step,
+            ..Step::new(bodies)
+        });
+    }
+    check(steps)
+}
+
+pub fn from_binary(contents: &[u8]) -> Result<Vec<Step>, String> {
+    let Some(mut rest) = contents.strip_prefix(BINARY_MAGIC.as_slice()) else {
+        return Err("Missing binary trajectory header".to_string());
+    };
+    let mut take = |length: usize| {
+        if rest.len() < length {
+            return Err("Binary trajectory ends in the middle of a step".to_string());
+        }
+        let (bytes, tail) = rest.split_at(length);
+        rest = tail;
+        Ok(bytes)
+    };
+    let f64 = |bytes: &[u8]| f64::from_le_bytes(bytes.try_into().unwrap());
+    let u32 = |bytes: &[u8]| u32::from_le_bytes(bytes.try_into().unwrap());
+
+    // Version 2 has no orbital elements after the state of each body, and
+    // the elements are left for the viewer to work out again either way.
+    let values = match take(1)?[0] {
+        2 => 7,
+        BINARY_VERSION => 11,
+        version => return Err(format!("Unsupported binary trajectory version {}", version)),
+    };
+    let mut steps = Vec::new();
+    while let Ok(time) = take(8) {
+        let time = f64(time);
+        let step = u32(take(4)?);
+        let count = u32(take(4)?) as usize;
+        let mut bodies = Vec::with_capacity(count);
+        for i in 0..count {
+            let values: Vec<f64> = take(values * 8)?.chunks(8).map(f64).collect();
+            bodies.push(
+                Body::new(
+                    dvec3(values[1], values[2], values[3]),
+                    COLORS[i % COLORS.len()],
+                )
+                .with_mass(values[0])
+                .with_velocity(dvec3(values[4], values[5], values[6])),
+            );
+        }
+        steps.push(Step {
+            time,
+            step,
+            ..Step::new(bodies)
+        });
+    }
+    check(steps)
+}
+
+/// Reads CSV written in headless mode, or otherwise CSV with columns
+/// `time,x1,y1,x2,y2,...` and an optional header. Bodies in generic CSV get a
+/// mass of 1 and velocities estimated from neighbouring rows.
+pub fn from_csv(contents: &str) -> Result<Vec<Step>, String> {
+    let mut lines = contents
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty())
+        .peekable();
+    let header = match lines.peek() {
+        Some((_, line)) if parse_row(line).is_err() => lines.next().map(|(_, line)| line),
+        _ => None,
+    };
+    // Files written before the orbital elements were added end with the
+    // state of the bodies.
+    let own = header.is_some_and(|header| {
+        let columns = header.trim().split(',').take(CSV_STATE_COLUMNS);
+        columns.eq(CSV_HEADER.split(',').take(CSV_STATE_COLUMNS))
+    });
+
+    let mut steps: Vec<Step> = Vec::new();
+    for (n, line) in lines {
+        // Only the state is read back, the elements follow from it.
+        let line = match line.match_indices(',').nth(CSV_STATE_COLUMNS - 1) {
+            Some((end, _)) if own => &line[..end],
+            _ => line,
+        };
+        let row = parse_row(line).map_err(|error| format!("Line {}: {}", n + 1, error))?;
+        if own {
+            let [time, step, body, mass, x, y, z, vx, vy, vz] = row[..] else {
+                return Err(format!("Line {}: expected 10 columns", n + 1));
+            };
+            // A step starts over at body 0, and steps can share an index
+            // in files written before each step was written only once.
+            let same_step = steps
+                .last()
+                .is_some_and(|last| body != 0.0 && last.time == time && last.step == step as u32);
+            if !same_step {
+                steps.push(Step {
+                    time,
+                    step: step as u32,
+                    ..Step::new(Vec::new())
+                });
+            }
+            let bodies = &mut steps.last_mut().unwrap().bodies;
+            if body != bodies.len() as f64 {
+                return Err(format!(
+                    "Line {}: expected body {} of step {}",
+                    n + 1,
+                    bodies.len(),
+                    step
+                ));
+            }
+            bodies.push(
+                Body::new(dvec3(x, y, z), COLORS[body as usize % COLORS.len()])
+                    .with_mass(mass)
+                    .with_velocity(dvec3(vx, vy, vz))
+                    .with_id(body as usize),
+            );
+        } else {
+            if row.len() < 3 || row.len() % 2 == 0 {
+                return Err(format!(
+                    "Line {}: expected a time column and an x and a y column per body",
+                    n + 1
+                ));
+            }
+            let bodies = row[1..]
+                .chunks(2)
+                .enumerate()
+                .map(|(i, xy)| Body::new(dvec3(xy[0], xy[1], 0.0), COLORS[i % COLORS.len()]))
+                .collect();
+            steps.push(Step {
+                time: row[0],
+                step: steps.len() as u32,
+                ..Step::new(bodies)
+            });
+        }
+    }
+    // Bodies can merge in a recording, but every row of generic CSV has to
+    // have the same columns.
+    if let Some(first) = steps.first().filter(|_| !own) {
+        let count = first.bodies.len();
+        if let Some(step) = steps.iter().find(|step| step.bodies.len() != count) {
+            return Err(format!(
+                "Step {} at t = {} has {} bodies, the first step has {}",
+                step.step,
+                step.time,
+                step.bodies.len(),
+                count
+            ));
+        }
+    }
+    if !own {
+        estimate_velocities(&mut steps);
+    }
+    check(steps)
+}
+
+fn parse_row(line: &str) -> Result<Vec<f64>, String> {
+    line.split(',')
+        .map(|value| {
+            value
+                .trim()
+                .parse::<f64>()
+                .map_err(|_| format!("'{}' is not a number", value.trim()))
+        })
+        .collect()
+}
+
+/// Sets velocities from the positions in the steps before and after.
+fn estimate_velocities(steps: &mut [Step]) {
+    if steps.len() < 2
+        || steps
+            .windows(2)
+            .any(|pair| pair[0].bodies.len() != pair[1].bodies.len())
+    {
+        return;
+    }
+    let positions: Vec<Vec<Position>> = steps
+        .iter()
+        .map(|step| step.bodies.iter().map(|body| body.position).collect())
+        .collect();
+    for n in 0..steps.len() {
+        let before = n.saturating_sub(1);
+        let after = (n + 1).min(steps.len() - 1);
+        let time = steps[after].time - steps[before].time;
+        if time <= 0.0 {
+            continue;
+        }
+        for (i, body) in steps[n].bodies.iter_mut().enumerate() {
+            body.velocity = (positions[after][i] - positions[before][i]) / time;
+        }
+    }
+}
+
+fn check(steps: Vec<Step>) -> Result<Vec<Step>, String> {
+    if steps.is_empty() {
+        return Err("Trajectory has no steps".to_string());
+    }
+    if steps.iter().any(|step| step.bodies.is_empty()) {
+        return Err("Trajectory has a step without bodies".to_string());
+    }
+    Ok(steps)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::export::{Exporter, Format};
+    use crate::integrator::Scheme;
+    use crate::presets::{Preset, PRESETS};
+    use crate::scenario::{Scenario, Settings};
+    use crate::simulate;
+    use crate::simulation::Simulation;
+    use crate::time_step::TimeStep;
+
+    #[test]
+    fn test_round_trip() {
+        let scenario = PRESETS[Preset::find("figure-eight").unwrap()].scenario();
+        let steps = simulate(
+            scenario.step,
+            0.05,
+            TimeStep::Fixed(0.001),
+            0.01,
+            &Scheme::VelocityVerlet,
+        )
+        .unwrap();
+        assert_round_trip(&steps);
+
+        // Samples of a binary as headless mode writes them, sampled as the
+        // run goes along.
+        let binary = Scenario::new(
+            "binary",
+            Settings {
+                time_step: 0.01,
+                steps: 100,
+                gravitational_constant: 1.0,
+                ..Settings::default()
+            },
+            vec![
+                Body::new(dvec3(-0.5, 0.0, 0.0), RED).with_velocity(dvec3(0.0, -0.5, 0.0)),
+                Body::new(dvec3(0.5, 0.0, 0.0), BLUE).with_velocity(dvec3(0.0, 0.5, 0.0)),
+            ],
+        );
+        let mut simulation = Simulation::from_settings(binary.step, &binary.settings);
+        let mut steps = Vec::new();
+        simulation
+            .run_until(binary.settings.duration(), |sample| {
+                steps.push(sample);
+                Ok(())
+            })
+            .unwrap();
+        assert_round_trip(&steps);
+
+        // Bodies that merged leave fewer in later steps.
+        let mut merged = steps[steps.len() - 1].clone().next_step(0.01);
+        merged.bodies.truncate(1);
+        steps.push(merged);
+        assert_round_trip(&steps);
+    }
+
+    fn assert_round_trip(steps: &[Step]) {
+        for format in Format::ALL {
+            let mut exporter = Exporter::new(Vec::new(), format).unwrap();
+            for step in steps.iter() {
+                exporter.write(step).unwrap();
+            }
+            let bytes = exporter.finish().unwrap();
+            let replayed = match format {
+                Format::Csv => from_csv(std::str::from_utf8(&bytes).unwrap()),
+                Format::JsonLines => from_json_lines(std::str::from_utf8(&bytes).unwrap()),
+                Format::Binary => from_binary(&bytes),
+            }
+            .unwrap();
+
+            assert_eq!(replayed.len(), steps.len(), "{}", format);
+            for (replayed, step) in replayed.iter().zip(steps.iter()) {
+                assert_eq!(replayed.step, step.step, "{}", format);
+                assert_eq!(replayed.bodies.len(), step.bodies.len(), "{}", format);
+                assert_eq!(replayed.time, step.time, "{}", format);
+                for (a, b) in replayed.bodies.iter().zip(step.bodies.iter()) {
+                    assert_eq!(a.mass, b.mass, "{}", format);
+                    assert_eq!(a.position, b.position, "{}", format);
+                    assert_eq!(a.velocity, b.velocity, "{}", format);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_binary_version_2() {
+        let mut bytes = BINARY_MAGIC.to_vec();
+        bytes.push(2);
+        bytes.extend(0.5f64.to_le_bytes());
+        bytes.extend(7u32.to_le_bytes());
+        bytes.extend(1u32.to_le_bytes());
+        for value in [2.0f64, 1.0, 0.0, 0.0, 0.0, 1.0, 0.0] {
+            bytes.extend(value.to_le_bytes());
+        }
+        let steps = from_binary(&bytes).unwrap();
+        assert_eq!(steps.len(), 1);
+        assert_eq!(steps[0].step, 7);
+        assert_eq!(steps[0].bodies[0].mass, 2.0);
+        assert_eq!(steps[0].bodies[0].velocity, dvec3(0.0, 1.0, 0.0));
+
+        bytes[4] = 1;
+        assert_eq!(
+            from_binary(&bytes),
+            Err("Unsupported binary trajectory version 1".to_string())
+        );
+    }
+
+    #[test]
+    fn test_repeated_step() {
+        // Headless runs used to write the initial conditions and the first
+        // sample both as step 0.
+        let csv = "time,step,body,mass,x,y,z,vx,vy,vz\n\
+                   0,0,0,1,-0.5,0,0,0,-0.5,0\n0,0,1,1,0.5,0,0,0,0.5,0\n\
+                   0,0,0,1,-0.49,-0.05,0,0,-0.5,0\n0,0,1,1,0.49,0.05,0,0,0.5,0\n";
+        let steps = from_csv(csv).unwrap();
+        assert_eq!(steps.len(), 2);
+        assert!(steps.iter().all(|step| step.bodies.len() == 2));
+        assert_eq!(steps[1].bodies[0].position, dvec3(-0.49, -0.05, 0.0));
+
+        let missing = csv.replacen("0,0,1,1,0.5", "0,0,2,1,0.5", 1);
+        assert_eq!(
+            from_csv(&missing),
+            Err("Line 3: expected body 1 of step 0".to_string())
+        );
+    }
+
+    #[test]
+    fn test_generic_csv() {
+        let steps = from_csv("t,x1,y1,x2,y2\n0,0,0,1,0\n1,0,1,1,0\n2,0,2,1,0\n").unwrap();
+        assert_eq!(steps.len(), 3);
+        assert_eq!(steps[2].step, 2);
+        assert_eq!(steps[1].bodies[0].position, dvec3(0.0, 1.0, 0.0));
+        assert_eq!(steps[1].bodies[0].velocity, dvec3(0.0, 1.0, 0.0));
+        assert_eq!(steps[1].bodies[1].velocity, DVec3::ZERO);
+
+        let old = from_csv("time,step,body,mass,x,y,z,vx,vy,vz\n0,0,0,2,1,0,0,0,1,0\n").unwrap();
+        assert_eq!(old[0].bodies[0].mass, 2.0);
+        assert_eq!(old[0].bodies[0].velocity, dvec3(0.0, 1.0, 0.0));
+
+        assert_eq!(
+            from_csv("0,0,0,1,0\n1,0,1\n"),
+            Err("Step 1 at t = 1 has 1 bodies, the first step has 2".to_string())
+        );
+        assert!(from_csv("0,0,0,1\n").is_err());
+        assert!(from_csv("t,x,y\n").is_err());
+    }
+}
@@ -0,0 +1,546 @@
+use crate::collision::{radius_from_density, Collisions};
+use crate::escape::Escapes;
+use crate::integrator::Scheme;
+use crate::section::Section;
+use crate::time_step::TimeStep;
+use crate::{
+    Body, Position, Step, ANIMATION_FPS, ANIMATION_LENGTH, GRAVITATIONAL_CONSTANT, STEPS, TIME_STEP,
+};
+use macroquad::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+/// Colours given to bodies that don't specify their own, in order.
+pub(crate) const COLORS: [Color; 8] = [RED, GREEN, BLUE, ORANGE, PURPLE, DARKGREEN, MAGENTA, BROWN];
+
+/// Everything about a run except the bodies themselves.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Settings {
+    pub time_step: f64,
+    pub steps: usize,
+    pub gravitational_constant: f64,
+    #[serde(default = "default_animation_fps")]
+    pub animation_fps: u32,
+    #[serde(default = "default_animation_length")]
+    pub animation_length: u32,
+    #[serde(default)]
+    pub integrator: Scheme,
+    /// Enables adaptive time stepping with this tolerance when set.
+    #[serde(default)]
+    pub tolerance: Option<f64>,
+    /// Plummer softening length, 0 for exact Newtonian gravity.
+    #[serde(default)]
+    pub softening: f64,
+    /// Logs close encounters between bodies nearer than this when set.
+    #[serde(default)]
+    pub encounter_distance: Option<f64>,
+    #[serde(default)]
+    pub collisions: Collisions,
+    /// Gives bodies without a radius the radius of a sphere of this density.
+    #[serde(default)]
+    pub density: Option<f64>,
+    /// Uses a Barnes-Hut tree with this opening angle instead of direct
+    /// summation when set.
+    #[serde(default)]
+    pub opening_angle: Option<f64>,
+    /// Estimates the maximal Lyapunov exponent along the run.
+    #[serde(default)]
+    pub lyapunov: bool,
+    #[serde(default)]
+    pub escapes: Escapes,
+    /// Records the crossings of this surface of section when set.
+    #[serde(default)]
+    pub section: Option<Section>,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Settings {
+            time_step: TIME_STEP,
+            steps: STEPS,
+            gravitational_constant: GRAVITATIONAL_CONSTANT,
+            animation_fps: ANIMATION_FPS,
+            animation_length: ANIMATION_LENGTH,
+            integrator: Scheme::default(),
+            tolerance: None,
+            softening: 0.0,
+            encounter_distance: None,
+            collisions: Collisions::default(),
+            density: None,
+            opening_angle: None,
+            lyapunov: false,
+            escapes: Escapes::default(),
+            section: None,
+        }
+    }
+}
+
+impl Settings {
+    pub fn duration(&self) -> f64 {
+        self.steps as f64 * self.time_step
+    }
+
+    /// Simulated time between two animation frames.
+    pub fn sample_interval(&self) -> f64 {
+        self.duration() / (self.animation_length * self.animation_fps) as f64
+    }
+
+    pub fn time_step(&self) -> TimeStep {
+        match self.tolerance {
+            Some(tolerance) => TimeStep::Adaptive {
+                tolerance,
+                min: self.time_step * 1e-3,
+                max: self.sample_interval(),
+            },
+            None => TimeStep::Fixed(self.time_step),
+        }
+    }
+
+    pub(crate) fn validate(&self) -> Result<(), String> {
+        let positive = [
+            ("time_step", self.time_step),
+            ("gravitational_constant", self.gravitational_constant),
+            ("tolerance", self.tolerance.unwrap_or(1.0)),
+            ("encounter_distance", self.encounter_distance.unwrap_or(1.0)),
+            ("density", self.density.unwrap_or(1.0)),
+            ("opening_angle", self.opening_angle.unwrap_or(1.0)),
+        ];
+        for (name, value) in positive {
+            if !(value.is_finite() && value > 0.0) {
+                return Err(format!("Setting {} must be positive, got {}", name, value));
+            }
+        }
+        if !(self.softening.is_finite() && self.softening >= 0.0) {
+            return Err(format!(
+                "Setting softening must not be negative, got {}",
+                self.softening
+            ));
+        }
+        let counts = [
+            ("steps", self.steps),
+            ("animation_fps", self.animation_fps as usize),
+            ("animation_length", self.animation_length as usize),
+        ];
+        for (name, value) in counts {
+            if value == 0 {
+                return Err(format!("Setting {} must be at least 1", name));
+            }
+        }
+        Ok(())
+    }
+}
+
+fn default_animation_fps() -> u32 {
+    ANIMATION_FPS
+}
+
+fn default_animation_length() -> u32 {
+    ANIMATION_LENGTH
+}
+
+/// Initial conditions and settings for a run, loadable from TOML or JSON.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Scenario {
+    pub name: String,
+    pub settings: Settings,
+    pub step: Step,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ScenarioFile {
+    #[serde(default)]
+    name: String,
+    settings: Settings,
+    bodies: Vec<BodyFile>,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct BodyFile {
+    mass: f64,
+    /// `[x, y]` in the plane or `[x, y, z]`.
+    position: Vec<f64>,
+    #[serde(default)]
+    velocity: Option<Vec<f64>>,
+    /// Hex colour such as `"#ff8800"`.
+    #[serde(default)]
+    color: Option<String>,
+    #[serde(default)]
+    radius: f64,
+}
+
+impl Scenario {
+    pub fn new(name: &str, settings: Settings, bodies: Vec<Body>) -> Self {
+        let mut scenario = Scenario {
+            name: name.to_string(),
+            settings,
+            step: Step::new(bodies)
+                .with_gravitational_constant(settings.gravitational_constant)
+                .with_softening(settings.softening)
+                .with_collisions(settings.collisions)
+                .with_opening_angle(settings.opening_angle),
+        };
+        if let Some(density) = settings.density {
+            scenario.set_density(density);
+        }
+        scenario
+    }
+
+    /// Gives every body without a radius the radius of a sphere of `density`.
+    pub fn set_density(&mut self, density: f64) {
+        self.settings.density = Some(density);
+        for body in self
+            .step
+            .bodies
+            .iter_mut()
+            .filter(|body| body.radius == 0.0)
+        {
+            body.radius = radius_from_density(body.mass, density);
+        }
+    }
+
+    /// Reads a scenario from a `.toml` or `.json` file.
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, String> {
+        let path = path.as_ref();
+        let contents = std::fs::read_to_string(path)
+            .map_err(|error| format!("Unable to read {}: {}", path.display(), error))?;
+        let scenario = match path.extension().and_then(|extension| extension.to_str()) {
+            Some("toml") => Scenario::from_toml(&contents),
+            Some("json") => Scenario::from_json(&contents),
+            _ => Err("Scenario files must end in .toml or .json".to_string()),
+        };
+        scenario.map_err(|error| format!("Invalid scenario {}: {}", path.display(), error))
+    }
+
+    pub fn from_toml(contents: &str) -> Result<Self, String> {
+        toml::from_str::<ScenarioFile>(contents)
+            .map_err(|error| error.to_string())?
+            .try_into()
+    }
+
+    pub fn from_json(contents: &str) -> Result<Self, String> {
+        serde_json::from_str::<ScenarioFile>(contents)
+            .map_err(|error| error.to_string())?
+            .try_into()
+    }
+
+    /// Writes the scenario to a `.toml` or `.json` file that `load` reads
+    /// back.
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), String> {
+        let path = path.as_ref();
+        let contents = match path.extension().and_then(|extension| extension.to_str()) {
+            Some("toml") => self.to_toml(),
+            Some("json") => self.to_json(),
+            _ => return Err("Scenario files must end in .toml or .json".to_string()),
+        };
+        std::fs::write(path, contents)
+            .map_err(|error| format!("Unable to write {}: {}", path.display(), error))
+    }
+
+    pub fn to_toml(&self) -> String {
+        toml::to_string(&ScenarioFile::from(self)).expect("Scenarios can always be serialised")
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(&ScenarioFile::from(self))
+            .expect("Scenarios can always be serialised")
+    }
+}
+
+impl From<&Scenario> for ScenarioFile {
+    /// Bodies of planar scenarios get vectors with 2 components.
+    fn from(scenario: &Scenario) -> Self {
+        let planar = scenario.step.is_planar();
+        let components = |vector: Position| {
+            let vector: [f64; 3] = vector.into();
+            vector[..if planar { 2 } else { 3 }].to_vec()
+        };
+        let bodies = scenario
+            .step
+            .bodies
+            .iter()
+            .map(|body| {
+                let [r, g, b, _]: [u8; 4] = body.color.into();
+                BodyFile {
+                    mass: body.mass,
+                    position: components(body.position),
+                    velocity: Some(components(body.velocity)),
+                    color: Some(format!("#{:02x}{:02x}{:02x}", r, g, b)),
+                    radius: body.radius,
+                }
+            })
+            .collect();
+        ScenarioFile {
+            name: scenario.name.clone(),
+            settings: scenario.settings,
+            bodies,
+        }
+    }
+}
+
+impl TryFrom<ScenarioFile> for Scenario {
+    type Error = String;
+
+    fn try_from(file: ScenarioFile) -> Result<Self, Self::Error> {
+        file.settings.validate()?;
+        if file.bodies.is_empty() {
+            return Err("Scenario has no bodies".to_string());
+        }
+
+        let mut bodies = Vec::with_capacity(file.bodies.len());
+        for (n, body) in file.bodies.into_iter().enumerate() {
+            let position = vector(&body.position)
+                .ok_or_else(|| format!("Body {} needs a position with 2 or 3 components", n + 1))?;
+            let velocity = match &body.velocity {
+                Some(velocity) => vector(velocity).ok_or_else(|| {
+                    format!("Body {} needs a velocity with 2 or 3 components", n + 1)
+                })?,
+                None => Position::ZERO,
+            };
+            if !body.mass.is_finite()
+                || !position.is_finite()
+                || !velocity.is_finite()
+                || !body.radius.is_finite()
+            {
+                return Err(format!(
+                    "Body {} has a value that is not a finite number",
+                    n + 1
+                ));
+            }
+            if body.mass <= 0.0 {
+                return Err(format!(
+                    "Body {} has a mass of {}, masses must be positive",
+                    n + 1,
+                    body.mass
+                ));
+            }
+            if body.radius < 0.0 {
+                return Err(format!(
+                    "Body {} has a radius of {}, radii must not be negative",
+                    n + 1,
+                    body.radius
+                ));
+            }
+            let color = match body.color {
+                Some(color) => parse_color(&color)
+                    .ok_or_else(|| format!("Body {} has an invalid color '{}'", n + 1, color))?,
+                None => COLORS[n % COLORS.len()],
+            };
+            bodies.push(
+                Body::new(position, color)
+                    .with_mass(body.mass)
+                    .with_velocity(velocity)
+                    .with_radius(body.radius),
+            );
+        }
+
+        for (i, a) in bodies.iter().enumerate() {
+            for (j, b) in bodies.iter().enumerate().skip(i + 1) {
+                if a.position == b.position {
+                    return Err(format!(
+                        "Bodies {} and {} are both at ({}, {}, {})",
+                        i + 1,
+                        j + 1,
+                        a.position.x,
+                        a.position.y,
+                        a.position.z
+                    ));
+                }
+            }
+        }
+
+        if let Some(section) = file.settings.section {
+            section.check(bodies.len())?;
+        }
+
+        Ok(Scenario::new(&file.name, file.settings, bodies))
+    }
+}
+
+/// Reads a vector given with 2 components, in the plane, or with 3.
+fn vector(values: &[f64]) -> Option<Position> {
+    match *values {
+        [x, y] => Some(dvec3(x, y, 0.0)),
+        [x, y, z] => Some(dvec3(x, y, z)),
+        _ => None,
+    }
+}
+
+fn parse_color(color: &str) -> Option<Color> {
+    let hex = color.strip_prefix('#')?;
+    if hex.len() != 6 {
+        return None;
+    }
+    u32::from_str_radix(hex, 16).ok().map(Color::from_hex)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FIGURE_EIGHT: &str = r##"
+        name = "Figure-eight"
+
+        [settings]
+        time_step = 0.0001
+        steps = 63259
+        gravitational_constant = 1.0
+        integrator = "verlet"
+
+        [[bodies]]
+        mass = 1.0
+        position = [-0.97000436, 0.24308753]
+        velocity = [0.466203685, 0.43236573]
+        color = "#ff0000"
+
+        [[bodies]]
+        mass = 1.0
+        position = [0.97000436, -0.24308753]
+        velocity = [0.466203685, 0.43236573]
+
+        [[bodies]]
+        mass = 1.0
+        position = [0.0, 0.0]
+        velocity = [-0.93240737, -0.86473146]
+    "##;
+
+    #[test]
+    fn test_load_toml() {
+        let scenario = Scenario::from_toml(FIGURE_EIGHT).unwrap();
+
+        assert_eq!(scenario.name, "Figure-eight");
+        assert_eq!(scenario.settings.integrator, Scheme::VelocityVerlet);
+        assert_eq!(scenario.settings.animation_fps, ANIMATION_FPS);
+        assert_eq!(scenario.step.gravitational_constant, 1.0);
+        assert_eq!(scenario.step.bodies.len(), 3);
+        assert_eq!(scenario.step.bodies[0].color, Color::from_hex(0xff0000));
+        assert_eq!(scenario.step.bodies[1].color, GREEN);
+        assert_eq!(
+            scenario.step.bodies[2].velocity,
+            dvec3(-0.93240737, -0.86473146, 0.0)
+        );
+    }
+
+    #[test]
+    fn test_three_components() {
+        let contents = FIGURE_EIGHT
+            .replace("[0.0, 0.0]", "[0.0, 0.0, 0.5]")
+            .replace("[-0.93240737, -0.86473146]", "[0.0, 0.0, -0.25]");
+        let scenario = Scenario::from_toml(&contents).unwrap();
+
+        assert_eq!(scenario.step.bodies[0].position.z, 0.0);
+        assert_eq!(scenario.step.bodies[2].position, dvec3(0.0, 0.0, 0.5));
+        assert_eq!(scenario.step.bodies[2].velocity, dvec3(0.0, 0.0, -0.25));
+        assert!(!scenario.step.is_planar());
+    }
+
+    #[test]
+    fn test_radius_and_density() {
+        let contents = FIGURE_EIGHT
+            .replace(
+                "integrator = \"verlet\"",
+                "integrator = \"verlet\"\ncollisions = \"merge\"\ndensity = 1000.0",
+            )
+            .replacen("color = \"#ff0000\"", "radius = 0.05", 1);
+        let scenario = Scenario::from_toml(&contents).unwrap();
+
+        assert_eq!(scenario.step.collisions, Collisions::Merge);
+        assert_eq!(scenario.step.bodies[0].radius, 0.05);
+        assert_eq!(
+            scenario.step.bodies[1].radius,
+            radius_from_density(1.0, 1000.0)
+        );
+
+        let negative = FIGURE_EIGHT.replacen("color = \"#ff0000\"", "radius = -1.0", 1);
+        assert_eq!(
+            Scenario::from_toml(&negative),
+            Err("Body 1 has a radius of -1, radii must not be negative".to_string())
+        );
+    }
+
+    #[test]
+    fn test_load_example() {
+        let scenario = Scenario::from_toml(include_str!("../scenarios/square.toml")).unwrap();
+        assert_eq!(scenario.step.bodies.len(), 4);
+        assert_eq!(scenario.settings.duration(), 20.0);
+    }
+
+    #[test]
+    fn test_save() {
+        let scenario = Scenario::from_toml(FIGURE_EIGHT).unwrap();
+        let toml = scenario.to_toml();
+        assert!(
+            toml.contains("position = [-0.97000436, 0.24308753]"),
+            "{}",
+            toml
+        );
+
+        for saved in [
+            Scenario::from_toml(&toml).unwrap(),
+            Scenario::from_json(&scenario.to_json()).unwrap(),
+        ] {
+            assert_eq!(saved.name, scenario.name);
+            assert_eq!(saved.settings, scenario.settings);
+            assert_eq!(saved.step.bodies[0].color, scenario.step.bodies[0].color);
+            for (saved, body) in saved.step.bodies.iter().zip(scenario.step.bodies.iter()) {
+                assert_eq!(saved.mass, body.mass);
+                assert_eq!(saved.position, body.position);
+                assert_eq!(saved.velocity, body.velocity);
+            }
+        }
+    }
+
+    #[test]
+    fn test_validation_errors() {
+        let missing_mass = FIGURE_EIGHT.replacen("mass = 1.0", "", 1);
+        assert!(Scenario::from_toml(&missing_mass)
+            .unwrap_err()
+            .contains("missing field `mass`"));
+
+        let zero_mass = FIGURE_EIGHT.replacen("mass = 1.0", "mass = 0.0", 1);
+        assert_eq!(
+            Scenario::from_toml(&zero_mass),
+            Err("Body 1 has a mass of 0, masses must be positive".to_string())
+        );
+
+        let coincident = FIGURE_EIGHT.replace("[0.97000436, -0.24308753]", "[0.0, 0.0]");
+        assert_eq!(
+            Scenario::from_toml(&coincident),
+            Err("Bodies 2 and 3 are both at (0, 0, 0)".to_string())
+        );
+
+        let short = FIGURE_EIGHT.replace("[0.0, 0.0]", "[0.0]");
+        assert_eq!(
+            Scenario::from_toml(&short),
+            Err("Body 3 needs a position with 2 or 3 components".to_string())
+        );
+
+        let json = r#"{"settings": {"steps": 10, "gravitational_constant": 1.0}, "bodies": []}"#;
+        assert!(Scenario::from_json(json)
+            .unwrap_err()
+            .contains("missing field `time_step`"));
+    }
+
+    #[test]
+    fn test_section_validation() {
+        let section = FIGURE_EIGHT.replace(
+            "integrator = \"verlet\"",
+            "integrator = \"verlet\"\nsection = \"y4=0+\"",
+        );
+        assert_eq!(
+            Scenario::from_toml(&section),
+            Err("Section y4=0+:x4,vx4 refers to a missing body, there are 3".to_string())
+        );
+        let section = section.replace("y4", "y1");
+        assert!(Scenario::from_toml(&section)
+            .unwrap()
+            .settings
+            .section
+            .is_some());
+        assert!(Scenario::from_toml(&section.replace("y1=0+", "m1=0"))
+            .unwrap_err()
+            .contains("not a mass"));
+    }
+}
@@ -0,0 +1,375 @@
+use crate::{Body, Parameter, Quantity, Step};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::str::FromStr;
+
+/// Which way the surface has to be crossed to count.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Direction {
+    Increasing,
+    Decreasing,
+    Both,
+}
+
+/// A surface of section where one parameter of a body, like the `y`
+/// position of body 1, takes a value, and the pair of parameters its
+/// crossings are plotted against.
+///
+/// Written like `y1=0+`, with `+` or `-` to count only crossings in that
+/// direction, and optionally followed by the axes as in `y1=0+:x1,vx1`.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct Section {
+    pub surface: Parameter,
+    pub value: f64,
+    pub direction: Direction,
+    /// Parameters along the horizontal and vertical axes of the plot.
+    pub axes: (Parameter, Parameter),
+}
+
+impl Section {
+    /// Crosses `surface` at `value` in `direction`, plotted against the
+    /// first other position component of the body and its velocity for a
+    /// position, and against the position along and across it for a
+    /// velocity.
+    pub fn new(surface: Parameter, value: f64, direction: Direction) -> Self {
+        let other = Parameter {
+            quantity: Quantity::Position,
+            component: if surface.component == 0 { 1 } else { 0 },
+            body: surface.body,
+        };
+        let axes = match surface.quantity {
+            Quantity::Velocity => (
+                Parameter {
+                    component: surface.component,
+                    ..other
+                },
+                other,
+            ),
+            _ => (
+                other,
+                Parameter {
+                    quantity: Quantity::Velocity,
+                    ..other
+                },
+            ),
+        };
+        Section {
+            surface,
+            value,
+            direction,
+            axes,
+        }
+    }
+
+    pub fn with_axes(self, axes: (Parameter, Parameter)) -> Self {
+        Section { axes, ..self }
+    }
+
+    /// Fraction of the way from `previous` to `current` at which the
+    /// surface is crossed in the right direction, if it is.
+    fn crossing(&self, previous: f64, current: f64) -> Option<f64> {
+        let increasing = previous < self.value && current >= self.value;
+        let decreasing = previous > self.value && current <= self.value;
+        let crossed = match self.direction {
+            Direction::Increasing => increasing,
+            Direction::Decreasing => decreasing,
+            Direction::Both => increasing || decreasing,
+        };
+        crossed.then(|| (self.value - previous) / (current - previous))
+    }
+
+    /// The point `step` is plotted at.
+    pub fn point(&self, step: &Step) -> Option<(f64, f64)> {
+        Some((
+            self.axes.0.value(&step.bodies)?,
+            self.axes.1.value(&step.bodies)?,
+        ))
+    }
+
+    /// Fails if the section refers to a body beyond the first `bodies`.
+    pub fn check(&self, bodies: usize) -> Result<(), String> {
+        let last = self
+            .surface
+            .body
+            .max(self.axes.0.body)
+            .max(self.axes.1.body);
+        if last < bodies {
+            Ok(())
+        } else {
+            Err(format!(
+                "Section {} refers to a missing body, there are {}",
+                self, bodies
+            ))
+        }
+    }
+}
+
+impl fmt::Display for Section {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let direction = match self.direction {
+            Direction::Increasing => "+",
+            Direction::Decreasing => "-",
+            Direction::Both => "",
+        };
+        write!(
+            f,
+            "{}={}{}:{},{}",
+            self.surface, self.value, direction, self.axes.0, self.axes.1
+        )
+    }
+}
+
+impl FromStr for Section {
+    type Err = String;
+
+    fn from_str(spec: &str) -> Result<Self, Self::Err> {
+        let invalid = || {
+            format!(
+                "Invalid section '{}', expected e.g. y1=0+ or y1=0+:x1,vx1",
+                spec
+            )
+        };
+        let (surface, axes) = match spec.split_once(':') {
+            Some((surface, axes)) => (surface, Some(axes)),
+            None => (spec, None),
+        };
+        let (parameter, value) = surface.split_once('=').ok_or_else(invalid)?;
+        let parameter: Parameter = parameter.parse()?;
+        if parameter.quantity == Quantity::Mass {
+            return Err(format!(
+                "Section {} must cross a position or velocity, not a mass",
+                spec
+            ));
+        }
+        let (value, direction) = if let Some(value) = value.strip_suffix('+') {
+            (value, Direction::Increasing)
+        } else if let Some(value) = value.strip_suffix('-') {
+            (value, Direction::Decreasing)
+        } else {
+            (value, Direction::Both)
+        };
+        let value: f64 = value.parse().map_err(|_| invalid())?;
+        if !value.is_finite() {
+            return Err(invalid());
+        }
+
+        let section = Section::new(parameter, value, direction);
+        match axes {
+            Some(axes) => {
+                let (x, y) = axes.split_once(',').ok_or_else(invalid)?;
+                Ok(section.with_axes((x.parse()?, y.parse()?)))
+            }
+            None => Ok(section),
+        }
+    }
+}
+
+impl TryFrom<String> for Section {
+    type Error = String;
+
+    fn try_from(spec: String) -> Result<Self, Self::Error> {
+        spec.parse()
+    }
+}
+
+impl From<Section> for String {
+    fn from(section: Section) -> Self {
+        section.to_string()
+    }
+}
+
+/// Collects the crossings of a section by watching a run step by step.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SectionRecorder {
+    section: Section,
+    previous_time: f64,
+    previous: Vec<Body>,
+    crossings: Vec<Step>,
+}
+
+impl SectionRecorder {
+    /// Starts watching from `step`, which can't itself be a crossing.
+    pub fn new(section: Section, step: &Step) -> Self {
+        SectionRecorder {
+            section,
+            previous_time: step.time,
+            previous: step.bodies.clone(),
+            crossings: Vec::new(),
+        }
+    }
+
+    pub fn section(&self) -> &Section {
+        &self.section
+    }
+
+    /// Every crossing found so far: the bodies interpolated linearly to
+    /// where the surface was crossed, in the order they happened.
+    pub fn crossings(&self) -> &[Step] {
+        &self.crossings
+    }
+
+    /// Checks whether the surface was crossed on the way to `step`,
+    /// returning the crossing if it was. A body that merged with another on
+    /// the way jumps to their centre of mass, so crossings of its surface
+    /// aren't looked for then.
+    pub fn update(&mut self, step: &Step) -> Option<&Step> {
+        let crossing = self.crossing(step);
+        let found = crossing.is_some();
+        self.previous_time = step.time;
+        self.previous.clone_from(&step.bodies);
+        self.crossings.extend(crossing);
+        self.crossings.last().filter(|_| found)
+    }
+
+    fn crossing(&self, step: &Step) -> Option<Step> {
+        let surface = self.section.surface;
+        let previous = &self.previous[Body::find(&self.previous, surface.body)?];
+        let current = &step.bodies[Body::find(&step.bodies, surface.body)?];
+        if previous.mass != current.mass {
+            return None;
+        }
+        let fraction = self
+            .section
+            .crossing(surface.value(&self.previous)?, surface.value(&step.bodies)?)?;
+        // Bodies merged away on the way are left out.
+        let bodies = step
+            .bodies
+            .iter()
+            .filter_map(|current| {
+                let previous = &self.previous[Body::find(&self.previous, current.id)?];
+                Some(Body {
+                    position: previous.position.lerp(current.position, fraction),
+                    velocity: previous.velocity.lerp(current.velocity, fraction),
+                    ..*current
+                })
+            })
+            .collect();
+        Some(Step {
+            time: self.previous_time + fraction * (step.time - self.previous_time),
+            step: step.step,
+            bodies,
+            gravitational_constant: step.gravitational_constant,
+            softening: step.softening,
+            collisions: step.collisions,
+            opening_angle: step.opening_angle,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::collision::Collisions;
+    use crate::integrator::Scheme;
+    use macroquad::prelude::*;
+    use std::f64::consts::TAU;
+
+    #[test]
+    fn test_parse() {
+        let section: Section = "y1=0+".parse().unwrap();
+        assert_eq!(section.surface.to_string(), "y1");
+        assert_eq!(section.value, 0.0);
+        assert_eq!(section.direction, Direction::Increasing);
+        assert_eq!(section.to_string(), "y1=0+:x1,vx1");
+        assert_eq!(section.to_string().parse(), Ok(section));
+
+        let section: Section = "vx2=-0.5-:x3,y3".parse().unwrap();
+        assert_eq!(section.value, -0.5);
+        assert_eq!(section.direction, Direction::Decreasing);
+        assert_eq!(section.axes.1.to_string(), "y3");
+        assert!(section.check(3).is_ok());
+        assert_eq!(
+            section.check(2),
+            Err("Section vx2=-0.5-:x3,y3 refers to a missing body, there are 2".to_string())
+        );
+        assert_eq!(
+            "vx2=1".parse::<Section>().unwrap().to_string(),
+            "vx2=1:x2,y2"
+        );
+
+        for spec in ["y1", "y1=", "y1=+", "y1=a", "y1=0:x1", "m1=1", "q1=0"] {
+            assert!(spec.parse::<Section>().is_err(), "{}", spec);
+        }
+    }
+
+    #[test]
+    fn test_crossings() {
+        // A body going round a circle of radius 1 at unit speed, sampled
+        // every 0.1 of a turn, crosses y = 0 upwards at x = 1 once a turn.
+        let circle = |time: f64| {
+            let (sin, cos) = time.sin_cos();
+            Step {
+                time,
+                ..Step::new(vec![
+                    Body::new(dvec3(cos, sin, 0.0), RED).with_velocity(dvec3(-sin, cos, 0.0))
+                ])
+            }
+        };
+        let section: Section = "y1=0+".parse().unwrap();
+        let mut recorder = SectionRecorder::new(section, &circle(0.05));
+        let mut found = 0;
+        for n in 1..=30 {
+            if recorder
+                .update(&circle(0.05 + n as f64 * 0.1 * TAU))
+                .is_some()
+            {
+                found += 1;
+            }
+        }
+        assert_eq!(found, 3);
+        for (n, crossing) in recorder.crossings().iter().enumerate() {
+            assert!((crossing.time - (n + 1) as f64 * TAU).abs() < 0.05);
+            let (x, vx) = section.point(crossing).unwrap();
+            assert!((x - 1.0).abs() < 0.1 && vx.abs() < 0.1, "{} {}", x, vx);
+            assert!(crossing.bodies[0].position.y.abs() < 1e-12);
+        }
+
+        let both = Section {
+            direction: Direction::Both,
+            ..section
+        };
+        let mut recorder = SectionRecorder::new(both, &circle(0.05));
+        for n in 1..=30 {
+            recorder.update(&circle(0.05 + n as f64 * 0.1 * TAU));
+        }
+        assert_eq!(recorder.crossings().len(), 6);
+    }
+
+    #[test]
+    fn test_crossings_after_merge() {
+        // The second body falls into the first early on, while the third
+        // goes round them at radius 3, crossing y = 0 upwards at x = 3 once
+        // every 2π 3^1.5 ≈ 32.6.
+        let (sin, cos) = 0.1f64.sin_cos();
+        let speed = 3f64.sqrt().recip();
+        let mut step = Step::new(vec![
+            Body::new(dvec3(0.0, 0.0, 0.0), RED).with_radius(0.1),
+            Body::new(dvec3(0.5, 0.0, 0.0), GREEN)
+                .with_mass(1e-3)
+                .with_radius(0.05),
+            Body::new(dvec3(3.0 * cos, 3.0 * sin, 0.0), BLUE)
+                .with_mass(1e-6)
+                .with_velocity(dvec3(-speed * sin, speed * cos, 0.0)),
+        ])
+        .with_gravitational_constant(1.0)
+        .with_collisions(Collisions::Merge);
+        let section: Section = "y3=0+".parse().unwrap();
+        let mut recorder = SectionRecorder::new(section, &step);
+        for _ in 0..7000 {
+            step.update(&Scheme::VelocityVerlet, 0.01).unwrap();
+            step = step.next_step(0.01);
+            recorder.update(&step);
+        }
+
+        assert_eq!(step.bodies.len(), 2);
+        assert_eq!(recorder.crossings().len(), 2);
+        for crossing in recorder.crossings() {
+            assert_eq!(crossing.bodies.len(), 2);
+            let (x, vx) = section.point(crossing).unwrap();
+            assert!((x - 3.0).abs() < 0.05 && vx.abs() < 0.05, "{} {}", x, vx);
+            assert_eq!(crossing.bodies[1].id, 2);
+            assert!(crossing.bodies[1].position.y.abs() < 1e-12);
+        }
+    }
+}
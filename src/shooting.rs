@@ -0,0 +1,300 @@
+use crate::integrator::Integrator;
+use crate::Step;
+use macroquad::prelude::*;
+
+/// Relative size of the nudges used to estimate how the return map changes
+/// with each unknown.
+const DIFFERENCE_STEP: f64 = 1e-7;
+/// Damping the first Levenberg-Marquardt step starts out with.
+const INITIAL_DAMPING: f64 = 1e-3;
+/// Damping beyond which no step can make the residual smaller.
+const MAX_DAMPING: f64 = 1e12;
+
+/// Initial conditions that come back to themselves after `period`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PeriodicOrbit {
+    pub step: Step,
+    pub period: f64,
+    /// Phase-space distance between the start and the end of one period.
+    pub residual: f64,
+    /// Newton iterations it took to get there.
+    pub iterations: usize,
+}
+
+/// Refines the initial conditions `guess` and its period into a periodic
+/// orbit by damped Newton shooting: the positions, velocities and period are
+/// adjusted until one period of integration lands back where it started,
+/// within `tolerance` in phase space.
+///
+/// Each period is integrated with `integrator` in as many equal steps as
+/// `time_step` fits into the guessed period, so that the return map changes
+/// smoothly with the period. Bodies in the xy plane stay in it, and masses
+/// are left alone.
+pub fn find(
+    guess: &Step,
+    period: f64,
+    time_step: f64,
+    integrator: &dyn Integrator,
+    tolerance: f64,
+    max_iterations: usize,
+) -> Result<PeriodicOrbit, String> {
+    if !(period.is_finite() && period > 0.0) {
+        return Err(format!("Period must be positive, got {}", period));
+    }
+    let shooting = Shooting {
+        guess,
+        steps: (period / time_step).round().max(1.0) as usize,
+        components: if guess.is_planar() { 2 } else { 3 },
+        integrator,
+    };
+
+    let mut unknowns = shooting.unknowns(guess, period);
+    let mut residuals = shooting.residuals(&unknowns)?;
+    let mut residual = norm(&residuals);
+    let mut damping = INITIAL_DAMPING;
+    for iteration in 0..max_iterations {
+        debug!(
+            "Shooting iteration {}: residual {:.4e}, period {}",
+            iteration,
+            residual,
+            unknowns.last().unwrap()
+        );
+        if residual <= tolerance {
+            return Ok(shooting.orbit(&unknowns, residual, iteration));
+        }
+
+        let jacobian = shooting.jacobian(&unknowns, &residuals)?;
+        // Normal equations of the least-squares problem, since the shifts
+        // and rotations of an orbit leave the system short of full rank.
+        let size = unknowns.len();
+        let mut normal = vec![vec![0.0; size]; size];
+        let mut gradient = vec![0.0; size];
+        for (row, &residual) in jacobian.iter().zip(residuals.iter()) {
+            for i in 0..size {
+                gradient[i] -= row[i] * residual;
+                for j in 0..size {
+                    normal[i][j] += row[i] * row[j];
+                }
+            }
+        }
+
+        loop {
+            let mut damped = normal.clone();
+            for (i, row) in damped.iter_mut().enumerate() {
+                row[i] += damping * normal[i][i].max(f64::MIN_POSITIVE);
+            }
+            let trial: Vec<f64> = match solve(damped, gradient.clone()) {
+                Some(change) => unknowns.iter().zip(change).map(|(x, dx)| x + dx).collect(),
+                None => Vec::new(),
+            };
+            let improved = match trial.last() {
+                Some(&period) if period > 0.0 => shooting
+                    .residuals(&trial)
+                    .ok()
+                    .filter(|trial| norm(trial) < residual),
+                _ => None,
+            };
+            if let Some(trial_residuals) = improved {
+                unknowns = trial;
+                residual = norm(&trial_residuals);
+                residuals = trial_residuals;
+                damping = (damping / 10.0).max(f64::EPSILON);
+                break;
+            }
+            damping *= 10.0;
+            if damping > MAX_DAMPING {
+                return Err(format!(
+                    "Shooting got stuck at a residual of {:.4e} after {} iterations",
+                    residual, iteration
+                ));
+            }
+        }
+    }
+    if residual <= tolerance {
+        Ok(shooting.orbit(&unknowns, residual, max_iterations))
+    } else {
+        Err(format!(
+            "Shooting reached a residual of {:.4e} in {} iterations, short of {:.4e}",
+            residual, max_iterations, tolerance
+        ))
+    }
+}
+
+/// The fixed parts of a shooting problem. The unknowns are the position and
+/// velocity components of every body followed by the period.
+struct Shooting<'a> {
+    guess: &'a Step,
+    steps: usize,
+    /// Components of each vector that may change, 2 to stay in the plane.
+    components: usize,
+    integrator: &'a dyn Integrator,
+}
+
+impl Shooting<'_> {
+    fn unknowns(&self, step: &Step, period: f64) -> Vec<f64> {
+        let mut unknowns = Vec::with_capacity(2 * self.components * step.bodies.len() + 1);
+        for body in step.bodies.iter() {
+            for vector in [body.position, body.velocity] {
+                unknowns.extend(&vector.to_array()[..self.components]);
+            }
+        }
+        unknowns.push(period);
+        unknowns
+    }
+
+    /// The guess with its positions and velocities taken from `unknowns`.
+    fn step(&self, unknowns: &[f64]) -> Step {
+        let mut step = self.guess.clone();
+        let mut values = unknowns.iter();
+        for body in step.bodies.iter_mut() {
+            for vector in [&mut body.position, &mut body.velocity] {
+                for component in 0..self.components {
+                    vector[component] = *values.next().unwrap();
+                }
+            }
+        }
+        step
+    }
+
+    /// How far one period of integration ends from where it started, for
+    /// every position and velocity component.
+    fn residuals(&self, unknowns: &[f64]) -> Result<Vec<f64>, String> {
+        let period = *unknowns.last().unwrap();
+        let time_step = period / self.steps as f64;
+        let mut step = self.step(unknowns);
+        for _ in 0..self.steps {
+            step.update(self.integrator, time_step)?;
+            step = step.next_step(time_step);
+        }
+        if step.bodies.len() != self.guess.bodies.len() {
+            return Err("Bodies collided before coming back around".to_string());
+        }
+        let end = self.unknowns(&step, period);
+        Ok(end
+            .iter()
+            .zip(unknowns)
+            .take(unknowns.len() - 1)
+            .map(|(end, start)| end - start)
+            .collect())
+    }
+
+    /// Forward difference estimate of the derivative of every residual,
+    /// one row per residual.
+    fn jacobian(&self, unknowns: &[f64], residuals: &[f64]) -> Result<Vec<Vec<f64>>, String> {
+        let mut jacobian = vec![vec![0.0; unknowns.len()]; residuals.len()];
+        for j in 0..unknowns.len() {
+            let mut nudged = unknowns.to_vec();
+            let nudge = DIFFERENCE_STEP * unknowns[j].abs().max(1.0);
+            nudged[j] += nudge;
+            let changed = self.residuals(&nudged)?;
+            for (row, (changed, residual)) in jacobian.iter_mut().zip(changed.iter().zip(residuals))
+            {
+                row[j] = (changed - residual) / nudge;
+            }
+        }
+        Ok(jacobian)
+    }
+
+    fn orbit(&self, unknowns: &[f64], residual: f64, iterations: usize) -> PeriodicOrbit {
+        PeriodicOrbit {
+            step: self.step(unknowns),
+            period: *unknowns.last().unwrap(),
+            residual,
+            iterations,
+        }
+    }
+}
+
+fn norm(values: &[f64]) -> f64 {
+    values.iter().map(|value| value * value).sum::<f64>().sqrt()
+}
+
+/// Solves `matrix · x = vector` by Gaussian elimination with partial
+/// pivoting, unless the matrix is singular.
+fn solve(mut matrix: Vec<Vec<f64>>, mut vector: Vec<f64>) -> Option<Vec<f64>> {
+    let size = vector.len();
+    for column in 0..size {
+        let pivot = (column..size)
+            .max_by(|&a, &b| matrix[a][column].abs().total_cmp(&matrix[b][column].abs()))?;
+        if matrix[pivot][column] == 0.0 || !matrix[pivot][column].is_finite() {
+            return None;
+        }
+        matrix.swap(column, pivot);
+        vector.swap(column, pivot);
+        for row in column + 1..size {
+            let (above, below) = matrix.split_at_mut(row);
+            let (pivot_row, current) = (&above[column], &mut below[0]);
+            let factor = current[column] / pivot_row[column];
+            for (value, pivot) in current[column..].iter_mut().zip(&pivot_row[column..]) {
+                *value -= factor * pivot;
+            }
+            vector[row] -= factor * vector[column];
+        }
+    }
+    let mut solution = vec![0.0; size];
+    for row in (0..size).rev() {
+        let sum: f64 = (row + 1..size).map(|k| matrix[row][k] * solution[k]).sum();
+        solution[row] = (vector[row] - sum) / matrix[row][row];
+    }
+    Some(solution)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::diagnostics::Diagnostics;
+    use crate::integrator::Scheme;
+    use crate::presets::{Preset, PRESETS};
+
+    #[test]
+    fn test_solve() {
+        let matrix = vec![vec![0.0, 2.0], vec![4.0, 1.0]];
+        assert_eq!(solve(matrix, vec![2.0, 9.0]), Some(vec![2.0, 1.0]));
+        assert_eq!(
+            solve(vec![vec![1.0, 2.0], vec![2.0, 4.0]], vec![1.0, 2.0]),
+            None
+        );
+    }
+
+    #[test]
+    fn test_figure_eight() {
+        let mut guess = PRESETS[Preset::find("figure-eight").unwrap()]
+            .scenario()
+            .step;
+        guess.bodies[2].velocity += dvec3(0.01, -0.01, 0.0);
+        guess.bodies[0].position.x += 0.01;
+        let time_step = 2e-3;
+        let scheme = Scheme::RungeKutta4;
+
+        let orbit = find(&guess, 6.2, time_step, &scheme, 1e-9, 20).unwrap();
+        assert!(orbit.residual <= 1e-9);
+        assert!(orbit.step.is_planar());
+        // Scaling an orbit up scales its period as |E|^(-3/2), so the
+        // nudged guess lands on a resized figure-eight.
+        let invariant =
+            |step: &Step, period: f64| period * Diagnostics::new(step).energy().abs().powf(1.5);
+        let figure_eight = PRESETS[Preset::find("figure-eight").unwrap()]
+            .scenario()
+            .step;
+        let expected = invariant(&figure_eight, 6.32591398);
+        let found = invariant(&orbit.step, orbit.period);
+        assert!(
+            (found / expected - 1.0).abs() < 1e-3,
+            "{} {}",
+            found,
+            expected
+        );
+
+        // Running it for a period brings it back.
+        let shooting = Shooting {
+            guess: &orbit.step,
+            steps: (6.2 / time_step).round() as usize,
+            components: 2,
+            integrator: &scheme,
+        };
+        let unknowns = shooting.unknowns(&orbit.step, orbit.period);
+        assert!(norm(&shooting.residuals(&unknowns).unwrap()) <= 1e-9);
+
+        assert!(find(&guess, -1.0, time_step, &scheme, 1e-9, 20).is_err());
+    }
+}
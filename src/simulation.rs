@@ -0,0 +1,311 @@
+use crate::checkpoint::Checkpoint;
+use crate::diagnostics::{Diagnostics, Drift};
+use crate::encounter::Encounters;
+use crate::escape::{Escape, EscapeDetector, Escapes};
+use crate::integrator::{Integrator, Scheme};
+use crate::lyapunov::{Estimate, Lyapunov};
+use crate::scenario::Settings;
+use crate::section::{Section, SectionRecorder};
+use crate::time_step::TimeStep;
+use crate::Step;
+use macroquad::prelude::*;
+
+/// An open-ended run that produces a copy of the bodies every
+/// `sample_interval` of simulated time after the initial conditions.
+pub struct Simulation<I> {
+    step: Step,
+    time_step: TimeStep,
+    sample_interval: f64,
+    integrator: I,
+    next_sample: f64,
+    initial: Step,
+    max_drift: Drift,
+    count: usize,
+    /// Logs close encounters when set.
+    encounters: Option<Encounters>,
+    /// Looks for escaping bodies when set.
+    escapes: Option<EscapeDetector>,
+    /// Ends the run at the first escape.
+    stop_on_escape: bool,
+    /// Records crossings of a surface of section when set.
+    section: Option<SectionRecorder>,
+    /// Shadows the run to estimate its Lyapunov exponent when set.
+    lyapunov: Option<Lyapunov>,
+    /// Set once the run has failed, after which it can't carry on.
+    error: Option<String>,
+}
+
+impl<I: Integrator> Simulation<I> {
+    pub fn new(step: Step, time_step: TimeStep, sample_interval: f64, integrator: I) -> Self {
+        Simulation {
+            next_sample: step.time + sample_interval,
+            initial: step.clone(),
+            step,
+            time_step,
+            sample_interval,
+            integrator,
+            max_drift: Drift::default(),
+            count: 0,
+            encounters: None,
+            escapes: None,
+            stop_on_escape: false,
+            section: None,
+            lyapunov: None,
+            error: None,
+        }
+    }
+
+    /// Logs every time two bodies pass within `distance` of each other.
+    pub fn with_encounter_distance(self, distance: f64) -> Self {
+        Simulation {
+            encounters: Some(Encounters::new(distance)),
+            ..self
+        }
+    }
+
+    /// Logs every body that escapes, ending the run at the first one if
+    /// `stop` is set.
+    pub fn with_escapes(self, stop: bool) -> Self {
+        Simulation {
+            escapes: Some(EscapeDetector::from_initial(&self.initial)),
+            stop_on_escape: stop,
+            ..self
+        }
+    }
+
+    /// Records every crossing of `section` from here on.
+    pub fn with_section(self, section: Section) -> Self {
+        Simulation {
+            section: Some(SectionRecorder::new(section, &self.step)),
+            ..self
+        }
+    }
+
+    /// Estimates the Lyapunov exponent from here on.
+    pub fn with_lyapunov(self) -> Self {
+        Simulation {
+            lyapunov: Some(Lyapunov::new(&self.step)),
+            ..self
+        }
+    }
+
+    /// The shadows estimating the Lyapunov exponent, if it is estimated.
+    pub fn lyapunov(&self) -> Option<&Lyapunov> {
+        self.lyapunov.as_ref()
+    }
+
+    /// The estimate of the Lyapunov exponent to publish with the bodies as
+    /// they are now, if it is estimated.
+    pub fn estimate(&self) -> Option<Estimate> {
+        let lyapunov = self.lyapunov.as_ref()?;
+        Some(lyapunov.estimate(&self.step))
+    }
+
+    /// Every crossing of the surface of section found so far, if one is
+    /// set.
+    pub fn crossings(&self) -> &[Step] {
+        self.section
+            .as_ref()
+            .map_or(&[], |section| section.crossings())
+    }
+
+    /// Every escape found so far, if escapes are looked for.
+    pub fn escapes(&self) -> &[Escape] {
+        self.escapes
+            .as_ref()
+            .map_or(&[], |escapes| escapes.escapes())
+    }
+
+    /// Whether the run has ended early because a body escaped.
+    pub fn stopped(&self) -> bool {
+        self.stop_on_escape && !self.escapes().is_empty()
+    }
+
+    pub fn time(&self) -> f64 {
+        self.step.time
+    }
+
+    /// The bodies as they are now, whether or not a sample is due.
+    pub fn current(&self) -> &Step {
+        &self.step
+    }
+
+    /// Number of integration steps taken so far.
+    pub fn steps_taken(&self) -> usize {
+        self.count
+    }
+
+    /// Captures the state of the run so it can be carried on later with
+    /// `Simulation::resume`. `settings` must be the ones the run was started
+    /// with.
+    pub fn checkpoint(&self, settings: Settings) -> Checkpoint {
+        Checkpoint {
+            settings,
+            step: self.step.clone(),
+            initial: self.initial.clone(),
+            next_sample: self.next_sample,
+            count: self.count,
+            escapes: self.escapes().to_vec(),
+            encounters: self
+                .encounters
+                .as_ref()
+                .map_or(Vec::new(), |encounters| encounters.ongoing().to_vec()),
+            lyapunov: self.lyapunov.clone(),
+            output: None,
+        }
+    }
+
+    /// Largest drift of the conserved quantities over the samples so far.
+    pub fn max_drift(&self) -> Drift {
+        self.max_drift
+    }
+
+    /// Runs a single integration step, returning a copy of the bodies when a
+    /// sample is due.
+    pub fn advance(&mut self) -> Result<Option<Step>, String> {
+        if let Some(error) = &self.error {
+            return Err(error.clone());
+        }
+        let time_step = self.time_step.next(&self.step);
+        if let Err(error) = self.step.update(&self.integrator, time_step) {
+            self.error = Some(error.clone());
+            return Err(error);
+        }
+        self.step = std::mem::take(&mut self.step).next_step(time_step);
+        if let Some(lyapunov) = self.lyapunov.as_mut() {
+            lyapunov.update(&self.step, &self.integrator, time_step);
+        }
+        if let Some(encounters) = self.encounters.as_mut() {
+            for encounter in encounters.update(&self.step) {
+                info!("{}", encounter);
+            }
+        }
+        if let Some(escapes) = self.escapes.as_mut() {
+            for escape in escapes.update(&self.step) {
+                info!("{}", escape);
+            }
+        }
+        if let Some(section) = self.section.as_mut() {
+            section.update(&self.step);
+        }
+        let sample = if self.step.time >= self.next_sample {
+            self.next_sample += self.sample_interval;
+            Some(self.step.clone())
+        } else if self.stopped() {
+            // The state the run ended in is always sampled.
+            Some(self.step.clone())
+        } else {
+            None
+        };
+        if sample.is_some() {
+            let drift = Diagnostics::new(&self.step).drift(&Diagnostics::new(&self.initial));
+            self.max_drift = self.max_drift.max(drift);
+        }
+
+        if self.count.is_multiple_of(1000000) {
+            let drift = Diagnostics::new(&self.step).drift(&Diagnostics::new(&self.initial));
+            debug!("Finished step {}, drift: {}", self.count, drift);
+        }
+        self.count += 1;
+
+        Ok(sample)
+    }
+
+    /// Keeps advancing until `duration` has passed, handing every sample to
+    /// `on_sample` and stopping early if it fails or a body escapes.
+    pub fn run_until(
+        &mut self,
+        duration: f64,
+        mut on_sample: impl FnMut(Step) -> Result<(), String>,
+    ) -> Result<(), String> {
+        while self.time() < duration && !self.stopped() {
+            if let Some(sample) = self.advance()? {
+                on_sample(sample)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Simulation<Scheme> {
+    /// Starts a run from `step` with the time stepping, integrator,
+    /// encounter logging, escape policy, surface of section and Lyapunov
+    /// estimate chosen in `settings`.
+    pub fn from_settings(step: Step, settings: &Settings) -> Self {
+        let simulation = Simulation::new(
+            step,
+            settings.time_step(),
+            settings.sample_interval(),
+            settings.integrator,
+        );
+        let simulation = match settings.encounter_distance {
+            Some(distance) => simulation.with_encounter_distance(distance),
+            None => simulation,
+        };
+        let simulation = match settings.escapes {
+            Escapes::Ignore => simulation,
+            Escapes::Log => simulation.with_escapes(false),
+            Escapes::Stop => simulation.with_escapes(true),
+        };
+        let simulation = match settings.section {
+            Some(section) => simulation.with_section(section),
+            None => simulation,
+        };
+        if settings.lyapunov {
+            simulation.with_lyapunov()
+        } else {
+            simulation
+        }
+    }
+
+    /// Carries on a run from a checkpoint, producing exactly the same samples
+    /// as the run it was taken from would have. Crossings of the surface of
+    /// section are only recorded from the checkpoint on.
+    pub fn resume(checkpoint: Checkpoint) -> Self {
+        let settings = checkpoint.settings;
+        let escapes = (settings.escapes != Escapes::Ignore).then(|| {
+            EscapeDetector::from_initial(&checkpoint.initial).with_escapes(checkpoint.escapes)
+        });
+        let encounters = settings
+            .encounter_distance
+            .map(|distance| Encounters::new(distance).with_ongoing(checkpoint.encounters));
+        let section = settings
+            .section
+            .map(|section| SectionRecorder::new(section, &checkpoint.step));
+        Simulation {
+            step: checkpoint.step,
+            time_step: settings.time_step(),
+            sample_interval: settings.sample_interval(),
+            integrator: settings.integrator,
+            next_sample: checkpoint.next_sample,
+            initial: checkpoint.initial,
+            max_drift: Drift::default(),
+            count: checkpoint.count,
+            encounters,
+            escapes,
+            stop_on_escape: settings.escapes == Escapes::Stop,
+            section,
+            lyapunov: checkpoint.lyapunov,
+            error: None,
+        }
+    }
+}
+
+/// Yields samples until the run fails, then the error once, or until a body
+/// escapes when set to stop.
+impl<I: Integrator> Iterator for Simulation<I> {
+    type Item = Result<Step, String>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.error.is_some() || self.stopped() {
+            return None;
+        }
+        loop {
+            match self.advance() {
+                Ok(Some(sample)) => return Some(Ok(sample)),
+                Ok(None) => (),
+                Err(error) => return Some(Err(error)),
+            }
+        }
+    }
+}
@@ -0,0 +1,200 @@
+use crate::integrator::Scheme;
+use crate::lyapunov::Estimate;
+use crate::scenario::Settings;
+use crate::simulation::Simulation;
+use crate::Step;
+
+#[cfg(not(target_arch = "wasm32"))]
+use std::sync::mpsc::{sync_channel, Receiver};
+
+/// Sampled steps produced while the animation is running.
+///
+/// Native builds simulate on a worker thread that blocks once `capacity`
+/// samples are waiting, while wasm builds, which have no threads, simulate on
+/// the main thread for a limited time each frame.
+pub struct Stream {
+    #[cfg(not(target_arch = "wasm32"))]
+    receiver: Receiver<Result<Sample, String>>,
+    #[cfg(target_arch = "wasm32")]
+    simulation: Simulation<Scheme>,
+    /// Crossings of the surface of section received so far.
+    crossings: Vec<Step>,
+    /// Estimate of the Lyapunov exponent at the latest sample, if the run
+    /// makes one.
+    lyapunov: Option<Estimate>,
+    /// Why the simulation stopped, if it failed.
+    error: Option<String>,
+}
+
+/// A sampled step, the crossings of the surface of section found since the
+/// previous one and the estimate of the Lyapunov exponent at it.
+#[cfg(not(target_arch = "wasm32"))]
+struct Sample {
+    step: Step,
+    crossings: Vec<Step>,
+    lyapunov: Option<Estimate>,
+}
+
+/// Seconds each frame may spend simulating on wasm.
+#[cfg(target_arch = "wasm32")]
+const FRAME_BUDGET: f64 = 0.01;
+
+impl Stream {
+    pub fn new(step: Step, settings: Settings, capacity: usize) -> Self {
+        Stream::from_simulation(Simulation::from_settings(step, &settings), capacity)
+    }
+
+    /// Streams the samples of a run that has already been set up, such as
+    /// one resumed from a checkpoint.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn from_simulation(simulation: Simulation<Scheme>, capacity: usize) -> Self {
+        let (sender, receiver) = sync_channel(capacity);
+        std::thread::spawn(move || {
+            let mut simulation = simulation;
+            let mut sent = 0;
+            while let Some(sample) = simulation.next() {
+                let sample = sample.map(|step| {
+                    let crossings = simulation.crossings()[sent..].to_vec();
+                    sent += crossings.len();
+                    Sample {
+                        step,
+                        crossings,
+                        lyapunov: simulation.estimate(),
+                    }
+                });
+                if sender.send(sample).is_err() {
+                    break;
+                }
+            }
+        });
+        Stream {
+            receiver,
+            crossings: Vec::new(),
+            lyapunov: None,
+            error: None,
+        }
+    }
+
+    /// Streams the samples of a run that has already been set up, such as
+    /// one resumed from a checkpoint.
+    #[cfg(target_arch = "wasm32")]
+    pub fn from_simulation(simulation: Simulation<Scheme>, _capacity: usize) -> Self {
+        Stream {
+            simulation,
+            crossings: Vec::new(),
+            lyapunov: None,
+            error: None,
+        }
+    }
+
+    /// Why the simulation stopped, once it has failed.
+    pub fn error(&self) -> Option<&str> {
+        self.error.as_deref()
+    }
+
+    /// Crossings of the surface of section up to the latest sample, if the
+    /// run records them.
+    pub fn crossings(&self) -> &[Step] {
+        &self.crossings
+    }
+
+    /// Estimate of the Lyapunov exponent at the latest sample, if the run
+    /// makes one.
+    pub fn lyapunov(&self) -> Option<&Estimate> {
+        self.lyapunov.as_ref()
+    }
+
+    /// Returns the next sample if it is ready.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn poll(&mut self) -> Option<Step> {
+        match self.receiver.try_recv().ok()? {
+            Ok(sample) => {
+                self.crossings.extend(sample.crossings);
+                self.lyapunov = sample.lyapunov;
+                Some(sample.step)
+            }
+            Err(error) => {
+                self.error = Some(error);
+                None
+            }
+        }
+    }
+
+    /// Returns the next sample if it can be computed within this frame's budget.
+    #[cfg(target_arch = "wasm32")]
+    pub fn poll(&mut self) -> Option<Step> {
+        let deadline = macroquad::time::get_time() + FRAME_BUDGET;
+        loop {
+            for _ in 0..1000 {
+                match self.simulation.advance() {
+                    Ok(Some(sample)) => {
+                        let crossings = &self.simulation.crossings()[self.crossings.len()..];
+                        self.crossings.extend_from_slice(crossings);
+                        self.lyapunov = self.simulation.estimate();
+                        return Some(sample);
+                    }
+                    Ok(None) => (),
+                    Err(error) => {
+                        self.error = Some(error);
+                        return None;
+                    }
+                }
+            }
+            if macroquad::time::get_time() > deadline {
+                return None;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::presets::Preset;
+    use crate::presets::PRESETS;
+    use crate::simulate;
+
+    #[test]
+    fn test_stream_matches_simulate() {
+        let scenario = PRESETS[Preset::find("figure-eight").unwrap()].scenario();
+        let settings = scenario.settings;
+        let expected = simulate(
+            scenario.step.clone(),
+            settings.sample_interval() * 10.0,
+            settings.time_step(),
+            settings.sample_interval(),
+            &settings.integrator,
+        )
+        .unwrap();
+
+        let mut stream = Stream::new(scenario.step, settings, 4);
+        let mut samples = Vec::new();
+        while samples.len() < expected.len() {
+            if let Some(sample) = stream.poll() {
+                samples.push(sample);
+            }
+        }
+        assert_eq!(samples, expected);
+    }
+
+    #[test]
+    fn test_stream_crossings() {
+        let scenario = PRESETS[Preset::find("figure-eight").unwrap()].scenario();
+        let settings = Settings {
+            section: Some("y1=0".parse().unwrap()),
+            ..scenario.settings
+        };
+        let mut simulation = Simulation::from_settings(scenario.step.clone(), &settings);
+        let samples = simulation.by_ref().take(600).count();
+
+        let mut stream = Stream::new(scenario.step, settings, 4);
+        let mut received = 0;
+        while received < samples {
+            if stream.poll().is_some() {
+                received += 1;
+            }
+        }
+        assert!(!simulation.crossings().is_empty());
+        assert_eq!(stream.crossings(), simulation.crossings());
+    }
+}
@@ -0,0 +1,499 @@
+use crate::diagnostics::Diagnostics;
+use crate::escape::{Escape, Escapes};
+use crate::scenario::Settings;
+use crate::simulation::Simulation;
+use crate::{Parameter, Quantity, Step};
+use std::io::{self, Write};
+use std::str::FromStr;
+
+/// Evenly spaced offsets to one parameter, written like `vx1=-0.1..0.1:5`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct GridAxis {
+    pub parameter: Parameter,
+    pub from: f64,
+    pub to: f64,
+    pub count: usize,
+}
+
+impl GridAxis {
+    pub fn offsets(&self) -> impl Iterator<Item = f64> + '_ {
+        (0..self.count).map(|n| match self.count {
+            1 => self.from,
+            count => self.from + (self.to - self.from) * n as f64 / (count - 1) as f64,
+        })
+    }
+}
+
+impl FromStr for GridAxis {
+    type Err = String;
+
+    fn from_str(axis: &str) -> Result<Self, Self::Err> {
+        let invalid = || {
+            format!(
+                "Invalid sweep '{}', expected <parameter>=<from>..<to>:<count>",
+                axis
+            )
+        };
+        let (parameter, range) = axis.split_once('=').ok_or_else(invalid)?;
+        let (range, count) = range.split_once(':').ok_or_else(invalid)?;
+        let (from, to) = range.split_once("..").ok_or_else(invalid)?;
+        let number = |value: &str| value.parse::<f64>().ok().filter(|value| value.is_finite());
+        let axis = GridAxis {
+            parameter: parameter.parse()?,
+            from: number(from).ok_or_else(invalid)?,
+            to: number(to).ok_or_else(invalid)?,
+            count: count.parse().map_err(|_| invalid())?,
+        };
+        if axis.count == 0 {
+            return Err(format!(
+                "Sweep '{}' needs at least one value",
+                axis.parameter
+            ));
+        }
+        Ok(axis)
+    }
+}
+
+/// Largest random offsets: positions and velocities are moved by up to the
+/// given amount in each component, masses by up to the given fraction, which
+/// is below 1 so that they stay positive.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Perturbation {
+    pub position: f64,
+    pub velocity: f64,
+    pub mass: f64,
+}
+
+impl Perturbation {
+    /// Sets one amplitude from a setting like `velocity=1e-6`.
+    pub fn set(&mut self, setting: &str) -> Result<(), String> {
+        let invalid = || {
+            format!(
+                "Invalid perturbation '{}', expected position, velocity or mass=<amplitude>",
+                setting
+            )
+        };
+        let (quantity, amplitude) = setting.split_once('=').ok_or_else(invalid)?;
+        let amplitude = amplitude
+            .parse::<f64>()
+            .ok()
+            .filter(|amplitude| amplitude.is_finite() && *amplitude >= 0.0)
+            .ok_or_else(invalid)?;
+        match quantity {
+            "position" => self.position = amplitude,
+            "velocity" => self.velocity = amplitude,
+            "mass" if amplitude >= 1.0 => {
+                return Err(format!(
+                    "Mass perturbation {} must be below 1 for masses to stay positive",
+                    amplitude
+                ))
+            }
+            "mass" => self.mass = amplitude,
+            _ => return Err(invalid()),
+        }
+        Ok(())
+    }
+}
+
+/// How the members of an ensemble are derived from a base scenario.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Sweep {
+    /// Every combination of the offsets along each axis.
+    Grid(Vec<GridAxis>),
+    /// Uniformly distributed random offsets to every body.
+    Random {
+        members: usize,
+        perturbation: Perturbation,
+        seed: u64,
+    },
+}
+
+/// Initial conditions of one run of an ensemble.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Member {
+    /// What was added to the base scenario.
+    pub offsets: Vec<(Parameter, f64)>,
+    pub step: Step,
+}
+
+impl Sweep {
+    /// The initial conditions of every member, derived from `base`.
+    pub fn members(&self, base: &Step) -> Result<Vec<Member>, String> {
+        let offsets = match self {
+            Sweep::Grid(axes) => axes.iter().fold(vec![Vec::new()], |members, axis| {
+                members
+                    .iter()
+                    .flat_map(|offsets: &Vec<(Parameter, f64)>| {
+                        axis.offsets().map(move |offset| {
+                            let mut offsets = offsets.clone();
+                            offsets.push((axis.parameter, offset));
+                            offsets
+                        })
+                    })
+                    .collect()
+            }),
+            Sweep::Random {
+                members,
+                perturbation,
+                seed,
+            } => {
+                let parameters = random_parameters(base, perturbation);
+                let mut random = Random::new(*seed);
+                (0..*members)
+                    .map(|_| {
+                        parameters
+                            .iter()
+                            .map(|&(parameter, amplitude)| {
+                                (parameter, amplitude * (2.0 * random.next() - 1.0))
+                            })
+                            .collect()
+                    })
+                    .collect()
+            }
+        };
+
+        offsets
+            .into_iter()
+            .map(|offsets: Vec<(Parameter, f64)>| {
+                let mut step = base.clone();
+                for &(parameter, offset) in offsets.iter() {
+                    parameter.apply(&mut step, offset)?;
+                }
+                if let Some(n) = step.bodies.iter().position(|body| body.mass <= 0.0) {
+                    return Err(format!(
+                        "Sweep leaves body {} without a positive mass",
+                        n + 1
+                    ));
+                }
+                Ok(Member { offsets, step })
+            })
+            .collect()
+    }
+}
+
+/// Parameters perturbed by a random sweep together with their largest
+/// offsets. Bodies in the plane are only moved within it.
+fn random_parameters(base: &Step, perturbation: &Perturbation) -> Vec<(Parameter, f64)> {
+    let components = if base.is_planar() { 2 } else { 3 };
+    let mut parameters = Vec::new();
+    for (body, state) in base.bodies.iter().enumerate() {
+        for (quantity, amplitude) in [
+            (Quantity::Position, perturbation.position),
+            (Quantity::Velocity, perturbation.velocity),
+        ] {
+            if amplitude > 0.0 {
+                parameters.extend((0..components).map(|component| {
+                    let parameter = Parameter {
+                        quantity,
+                        component,
+                        body,
+                    };
+                    (parameter, amplitude)
+                }));
+            }
+        }
+        if perturbation.mass > 0.0 {
+            let parameter = Parameter {
+                quantity: Quantity::Mass,
+                component: 0,
+                body,
+            };
+            parameters.push((parameter, perturbation.mass * state.mass));
+        }
+    }
+    parameters
+}
+
+/// Xorshift generator giving reproducible sweeps for a seed.
+struct Random(u64);
+
+impl Random {
+    fn new(seed: u64) -> Self {
+        // Xorshift gets stuck at zero, and nearby seeds should still differ
+        // from the first number on.
+        Random(seed.wrapping_mul(0x9e3779b97f4a7c15) | 1)
+    }
+
+    /// A number in `[0, 1)`.
+    fn next(&mut self) -> f64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        (self.0 >> 11) as f64 / (1u64 << 53) as f64
+    }
+}
+
+/// Outcome of one member of an ensemble.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Summary {
+    /// The first body to escape.
+    pub escape: Option<Escape>,
+    /// Closest any two bodies came at the end of an integration step.
+    pub min_separation: f64,
+    /// Largest relative energy drift in the samples.
+    pub energy_drift: f64,
+    /// Time the run got to, short of the duration if it failed.
+    pub end_time: f64,
+    /// Estimated maximal Lyapunov exponent, when asked for.
+    pub lyapunov_exponent: Option<f64>,
+    pub error: Option<String>,
+}
+
+/// Runs `step` for the duration in `settings` and sums up how it went.
+pub fn summarise(step: Step, settings: &Settings) -> Summary {
+    let initial = Diagnostics::new(&step);
+    let mut summary = Summary {
+        escape: None,
+        min_separation: min_separation(&step),
+        energy_drift: 0.0,
+        end_time: step.time,
+        lyapunov_exponent: None,
+        error: None,
+    };
+
+    let mut simulation = Simulation::from_settings(step, settings);
+    if settings.escapes == Escapes::Ignore {
+        simulation = simulation.with_escapes(false);
+    }
+    while simulation.time() < settings.duration() && !simulation.stopped() {
+        let sample = match simulation.advance() {
+            Ok(sample) => sample,
+            Err(error) => {
+                summary.error = Some(error);
+                break;
+            }
+        };
+        summary.min_separation = summary
+            .min_separation
+            .min(min_separation(simulation.current()));
+        if let Some(sample) = sample {
+            let drift = Diagnostics::new(&sample).drift(&initial);
+            summary.energy_drift = summary.energy_drift.max(drift.energy);
+        }
+    }
+    summary.escape = simulation.escapes().first().copied();
+    summary.end_time = simulation.time();
+    summary.lyapunov_exponent = simulation
+        .lyapunov()
+        .and_then(|lyapunov| lyapunov.exponent());
+    summary
+}
+
+/// Runs every member, on as many threads as there are cores in native
+/// builds. Summaries come back in the order of the members.
+pub fn run(members: &[Member], settings: &Settings) -> Vec<Summary> {
+    #[cfg(not(target_arch = "wasm32"))]
+    {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        let threads = std::thread::available_parallelism().map_or(1, |threads| threads.get());
+        let next = AtomicUsize::new(0);
+        let mut summaries: Vec<(usize, Summary)> = std::thread::scope(|scope| {
+            let workers: Vec<_> = (0..threads.min(members.len()))
+                .map(|_| {
+                    scope.spawn(|| {
+                        let mut summaries = Vec::new();
+                        loop {
+                            let n = next.fetch_add(1, Ordering::Relaxed);
+                            let Some(member) = members.get(n) else {
+                                return summaries;
+                            };
+                            summaries.push((n, summarise(member.step.clone(), settings)));
+                        }
+                    })
+                })
+                .collect();
+            workers
+                .into_iter()
+                .flat_map(|worker| worker.join().expect("Sweep worker panicked"))
+                .collect()
+        });
+        summaries.sort_by_key(|(n, _)| *n);
+        summaries.into_iter().map(|(_, summary)| summary).collect()
+    }
+    #[cfg(target_arch = "wasm32")]
+    {
+        members
+            .iter()
+            .map(|member| summarise(member.step.clone(), settings))
+            .collect()
+    }
+}
+
+/// Writes one CSV row per member with its offsets and summary, bodies
+/// counted from 1.
+pub fn write_table(
+    mut writer: impl Write,
+    members: &[Member],
+    summaries: &[Summary],
+) -> io::Result<()> {
+    let parameters: Vec<String> = members
+        .first()
+        .map(|member| {
+            member
+                .offsets
+                .iter()
+                .map(|(parameter, _)| parameter.to_string())
+                .collect()
+        })
+        .unwrap_or_default();
+    write!(writer, "member")?;
+    for parameter in parameters.iter() {
+        write!(writer, ",{}", parameter)?;
+    }
+    writeln!(
+        writer,
+        ",escape_time,escaper,escape_speed,min_separation,energy_drift,end_time,lyapunov_exponent,error"
+    )?;
+
+    for (n, (member, summary)) in members.iter().zip(summaries).enumerate() {
+        write!(writer, "{}", n + 1)?;
+        for (_, offset) in member.offsets.iter() {
+            write!(writer, ",{}", offset)?;
+        }
+        match summary.escape {
+            Some(escape) => write!(
+                writer,
+                ",{},{},{}",
+                escape.time,
+                escape.body + 1,
+                escape.velocity.length()
+            )?,
+            None => write!(writer, ",,,")?,
+        }
+        write!(
+            writer,
+            ",{},{},{},",
+            summary.min_separation, summary.energy_drift, summary.end_time
+        )?;
+        if let Some(exponent) = summary.lyapunov_exponent {
+            write!(writer, "{}", exponent)?;
+        }
+        write!(writer, ",")?;
+        if let Some(error) = &summary.error {
+            write!(writer, "\"{}\"", error.replace('"', "\"\""))?;
+        }
+        writeln!(writer)?;
+    }
+    writer.flush()
+}
+
+/// Smallest distance between any two bodies, infinite with fewer than two.
+fn min_separation(step: &Step) -> f64 {
+    let mut separation = f64::INFINITY;
+    for (i, a) in step.bodies.iter().enumerate() {
+        for b in step.bodies[i + 1..].iter() {
+            separation = separation.min(a.position.distance(b.position));
+        }
+    }
+    separation
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::presets::{Preset, PRESETS};
+
+    #[test]
+    fn test_grid() {
+        let base = PRESETS[Preset::find("figure-eight").unwrap()]
+            .scenario()
+            .step;
+        let sweep = Sweep::Grid(vec![
+            "x1=-0.1..0.1:3".parse().unwrap(),
+            "m2=0..0.5:2".parse().unwrap(),
+        ]);
+        let members = sweep.members(&base).unwrap();
+
+        assert_eq!(members.len(), 6);
+        assert_eq!(members[5].offsets.len(), 2);
+        assert_eq!(members[5].offsets[0].1, 0.1);
+        assert_eq!(members[5].offsets[1].1, 0.5);
+        assert_eq!(
+            members[5].step.bodies[0].position.x,
+            base.bodies[0].position.x + 0.1
+        );
+        assert_eq!(members[5].step.bodies[1].mass, 1.5);
+
+        let missing = Sweep::Grid(vec!["x4=0..1:2".parse().unwrap()]);
+        assert!(missing.members(&base).is_err());
+    }
+
+    #[test]
+    fn test_random() {
+        let base = PRESETS[Preset::find("figure-eight").unwrap()]
+            .scenario()
+            .step;
+        let sweep = |seed| Sweep::Random {
+            members: 10,
+            perturbation: Perturbation {
+                position: 1e-3,
+                velocity: 0.0,
+                mass: 0.1,
+            },
+            seed,
+        };
+        let members = sweep(1).members(&base).unwrap();
+
+        assert_eq!(members, sweep(1).members(&base).unwrap());
+        assert_ne!(members, sweep(2).members(&base).unwrap());
+        assert_eq!(members.len(), 10);
+        for member in members.iter() {
+            // x and y of each body and its mass, staying in the plane.
+            assert_eq!(member.offsets.len(), 9);
+            assert!(member.step.is_planar());
+            for (a, b) in member.step.bodies.iter().zip(base.bodies.iter()) {
+                assert!((a.position - b.position).abs().max_element() <= 1e-3);
+                assert_eq!(a.velocity, b.velocity);
+                assert!((a.mass - b.mass).abs() <= 0.1);
+            }
+        }
+
+        let mut perturbation = Perturbation::default();
+        perturbation.set("mass=0.999").unwrap();
+        assert_eq!(perturbation.mass, 0.999);
+        let sweep = Sweep::Random {
+            members: 1000,
+            perturbation,
+            seed: 3,
+        };
+        assert!(sweep.members(&base).is_ok());
+        assert_eq!(
+            perturbation.set("mass=1"),
+            Err("Mass perturbation 1 must be below 1 for masses to stay positive".to_string())
+        );
+        assert!(perturbation.set("velocity=-1").is_err());
+    }
+
+    #[test]
+    fn test_run() {
+        let scenario = PRESETS[Preset::find("figure-eight").unwrap()].scenario();
+        let settings = Settings {
+            steps: 1000,
+            ..scenario.settings
+        };
+        let sweep = Sweep::Grid(vec!["vx3=0..0.01:3".parse().unwrap()]);
+        let members = sweep.members(&scenario.step).unwrap();
+        let summaries = run(&members, &settings);
+
+        assert_eq!(summaries.len(), 3);
+        for (member, summary) in members.iter().zip(summaries.iter()) {
+            assert_eq!(summary, &summarise(member.step.clone(), &settings));
+            assert_eq!(summary.error, None);
+            assert_eq!(summary.escape, None);
+            assert!(summary.min_separation > 0.5 && summary.min_separation < 1.5);
+            assert!(summary.energy_drift < 1e-4, "{}", summary.energy_drift);
+        }
+
+        let mut table = Vec::new();
+        write_table(&mut table, &members, &summaries).unwrap();
+        let table = String::from_utf8(table).unwrap();
+        let lines: Vec<_> = table.lines().collect();
+        assert_eq!(
+            lines[0],
+            "member,vx3,escape_time,escaper,escape_speed,min_separation,energy_drift,end_time,lyapunov_exponent,error"
+        );
+        assert_eq!(lines.len(), 4);
+        assert!(lines[3].starts_with("3,0.01,,,,"));
+    }
+}
@@ -0,0 +1,90 @@
+use crate::Step;
+
+/// Chooses how far each integration step advances the simulation.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TimeStep {
+    Fixed(f64),
+    /// Scales the shortest dynamical time between any pair of bodies by
+    /// `tolerance`, so steps shrink during close encounters and grow when the
+    /// bodies are far apart.
+    Adaptive {
+        tolerance: f64,
+        min: f64,
+        max: f64,
+    },
+}
+
+impl TimeStep {
+    pub fn next(&self, step: &Step) -> f64 {
+        match *self {
+            TimeStep::Fixed(time_step) => time_step,
+            TimeStep::Adaptive {
+                tolerance,
+                min,
+                max,
+            } => (tolerance * dynamical_time(step)).clamp(min, max),
+        }
+    }
+}
+
+/// Smallest of the crossing and free-fall times over all pairs of bodies.
+fn dynamical_time(step: &Step) -> f64 {
+    let mut shortest = f64::INFINITY;
+    for (i, a) in step.bodies.iter().enumerate() {
+        for b in step.bodies[i + 1..].iter() {
+            let distance = a.position.distance(b.position);
+            let speed = a.velocity.distance(b.velocity);
+            let free_fall =
+                (distance.powi(3) / (step.gravitational_constant * (a.mass + b.mass))).sqrt();
+            shortest = shortest.min(free_fall).min(distance / speed);
+        }
+    }
+    shortest
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::integrator::Scheme;
+    use crate::{simulate, Body};
+    use macroquad::prelude::*;
+
+    #[test]
+    fn test_adaptive_steps_shrink_during_close_approach() {
+        let first = Body::new(dvec3(-0.5, 0.0, 0.0), RED);
+        let mut second = Body::new(dvec3(0.5, 0.0, 0.0), BLUE);
+        second.velocity = dvec3(0.0, 2e-6, 0.0);
+        let time_step = TimeStep::Adaptive {
+            tolerance: 0.01,
+            min: 1.0,
+            max: 1e4,
+        };
+
+        let steps = simulate(
+            Step::new(vec![first, second]),
+            2e6,
+            time_step,
+            1.0,
+            &Scheme::VelocityVerlet,
+        )
+        .unwrap();
+
+        for pair in steps.windows(2) {
+            let time_step = pair[1].time - pair[0].time;
+            let distance = pair[0].bodies[0]
+                .position
+                .distance(pair[0].bodies[1].position);
+            assert!(time_step > 0.0);
+            if distance < 0.1 {
+                assert!(time_step < 100.0, "{} at distance {}", time_step, distance);
+            }
+            if distance > 0.9 {
+                assert!(time_step > 500.0, "{} at distance {}", time_step, distance);
+            }
+        }
+        assert!(steps.len() < 20000, "{}", steps.len());
+        assert!(steps
+            .iter()
+            .any(|step| step.bodies[0].position.distance(step.bodies[1].position) < 0.1));
+    }
+}
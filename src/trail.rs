@@ -0,0 +1,161 @@
+use crate::orbit::OrbitCamera;
+use crate::{Body, Position, Step};
+use macroquad::prelude::*;
+use std::fmt;
+use std::str::FromStr;
+
+/// Faintest alpha used at the old end of a trail.
+const MIN_ALPHA: f32 = 0.05;
+
+/// How many sampled positions a trail keeps.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TrailLength {
+    Limited(usize),
+    Infinite,
+}
+
+impl Default for TrailLength {
+    fn default() -> Self {
+        TrailLength::Limited(300)
+    }
+}
+
+impl fmt::Display for TrailLength {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TrailLength::Limited(length) => write!(f, "{}", length),
+            TrailLength::Infinite => write!(f, "infinite"),
+        }
+    }
+}
+
+impl FromStr for TrailLength {
+    type Err = String;
+
+    fn from_str(length: &str) -> Result<Self, Self::Err> {
+        match length {
+            "infinite" => Ok(TrailLength::Infinite),
+            _ => length.parse().map(TrailLength::Limited).map_err(|_| {
+                format!(
+                    "Invalid trail length '{}', expected a number or 'infinite'",
+                    length
+                )
+            }),
+        }
+    }
+}
+
+/// Recent positions of every body, drawn as lines fading out with age.
+pub struct Trails {
+    length: TrailLength,
+}
+
+impl Trails {
+    pub fn new(length: TrailLength) -> Self {
+        Trails { length }
+    }
+
+    /// The steps covered by a trail ending at the last step of `history`.
+    fn window<'a>(&self, history: &'a [Step]) -> &'a [Step] {
+        match self.length {
+            TrailLength::Limited(length) => &history[history.len().saturating_sub(length)..],
+            TrailLength::Infinite => history,
+        }
+    }
+
+    /// Draws the trails leading up to the last step of `history` projected
+    /// onto the xy plane, with lines sized in units of `pixel_size`.
+    pub fn draw(&self, history: &[Step], pixel_size: f32) {
+        self.segments(history, |from, to, color| {
+            let from = from.truncate().as_vec2();
+            let to = to.truncate().as_vec2();
+            draw_line(from.x, from.y, to.x, to.y, 1.5 * pixel_size, color);
+        });
+    }
+
+    /// Draws the trails leading up to the last step of `history` seen
+    /// through `camera`.
+    pub fn draw_3d(&self, history: &[Step], camera: &OrbitCamera) {
+        self.segments(history, |from, to, color| {
+            draw_line_3d(camera.to_view(from), camera.to_view(to), color);
+        });
+    }
+
+    /// Calls `draw` with the ends and colour of every segment of the trails,
+    /// oldest first.
+    fn segments(&self, history: &[Step], mut draw: impl FnMut(Position, Position, Color)) {
+        let window = self.window(history);
+        let segments = window.len().saturating_sub(1);
+        for (n, pair) in window.windows(2).enumerate() {
+            let age = (n + 1) as f32 / segments as f32;
+            // Bodies merged away between the two steps end their trails.
+            for to in &pair[1].bodies {
+                let Some(from) = Body::find(&pair[0].bodies, to.id).map(|n| &pair[0].bodies[n])
+                else {
+                    continue;
+                };
+                let color = Color {
+                    a: to.color.a * (MIN_ALPHA + (1.0 - MIN_ALPHA) * age),
+                    ..to.color
+                };
+                draw(from.position, to.position, color);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_limited_length() {
+        let history: Vec<Step> = (0..4)
+            .map(|x| Step::new(vec![Body::new(dvec3(x as f64, 0.0, 0.0), RED)]))
+            .collect();
+
+        assert_eq!(
+            Trails::new(TrailLength::Limited(2)).window(&history),
+            &history[2..]
+        );
+        assert_eq!(
+            Trails::new(TrailLength::Limited(9)).window(&history),
+            &history[..]
+        );
+        assert_eq!(
+            Trails::new(TrailLength::Infinite).window(&history),
+            &history[..]
+        );
+    }
+
+    #[test]
+    fn test_segments_follow_merged_bodies() {
+        let before = Step::new(vec![
+            Body::new(dvec3(0.0, 0.0, 0.0), RED),
+            Body::new(dvec3(1.0, 0.0, 0.0), GREEN),
+            Body::new(dvec3(2.0, 0.0, 0.0), BLUE),
+        ]);
+        // The first two bodies merged, so the third one is now second.
+        let mut after = before.clone();
+        after.bodies.remove(1);
+        after.bodies[1].position.y = 1.0;
+
+        let mut segments = Vec::new();
+        Trails::new(TrailLength::Infinite)
+            .segments(&[before, after], |from, to, _| segments.push((from, to)));
+        assert_eq!(
+            segments,
+            [
+                (dvec3(0.0, 0.0, 0.0), dvec3(0.0, 0.0, 0.0)),
+                (dvec3(2.0, 0.0, 0.0), dvec3(2.0, 1.0, 0.0)),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parse_length() {
+        assert_eq!("infinite".parse(), Ok(TrailLength::Infinite));
+        assert_eq!("20".parse(), Ok(TrailLength::Limited(20)));
+        assert!("forever".parse::<TrailLength>().is_err());
+    }
+}